@@ -13,8 +13,8 @@ const coordToKey = ({ ox, oy, ix, iy }) => [ox, oy, ix, iy].join(',');
 const gridMap = (grid, fn) => grid.flatMap(
     (row, x) => row.map((cell, y) => fn(cell, x, y)));
 
-const isInWinningTriple = (winningTriple, x, y) => Array.isArray(winningTriple)
-    && winningTriple.find(([wx, wy]) => wx === x && wy === y);
+const isInWinningLines = (winningLines, x, y) => Array.isArray(winningLines)
+    && winningLines.some(({ squares }) => squares.some(([wx, wy]) => wx === x && wy === y));
 
 const useGame = () => {
     const [game, setGame] = useState(ultimateTicTacToe.initialize);
@@ -34,7 +34,7 @@ const useGame = () => {
 export default () => {
     const [game, moves, playMove] = useGame();
 
-    const cells = gridMap(game.grid, ({grid, completed, winningTriple}, ox, oy) => {
+    const cells = gridMap(game.grid, ({grid, completed, winningLines}, ox, oy) => {
         const key = coordToKey({ ox, oy });
 
         const isActive = (
@@ -44,7 +44,7 @@ export default () => {
             <InnerGrid highlight={isActive} key={key}>
                 {gridMap(grid, (cell, ix, iy) => {
                     const key = coordToKey({ ox, oy, ix, iy });
-                    const darker = isInWinningTriple(winningTriple, ix, iy);
+                    const darker = isInWinningLines(winningLines, ix, iy);
                     switch (cell) {
                         case "o":
                             return <O key={key} darker={darker} />
@@ -61,7 +61,7 @@ export default () => {
             </InnerGrid>
         );
 
-        const darker = isInWinningTriple(game.winningTriple, ox, oy);
+        const darker = isInWinningLines(game.winningLines, ox, oy);
         switch (completed) {
             case "x":
                 return (
@@ -2,7 +2,7 @@ import React from 'react';
 import styled, { createGlobalStyle } from 'styled-components';
 
 const range = length => [...Array(length).keys()];
-const grid3x3 = range(3).flatMap(y => range(3).map(x => ({ x, y })));
+const gridNxN = size => range(size).flatMap(y => range(size).map(x => ({ x, y })));
 
 const colorPalette = {
     x: {
@@ -57,7 +57,7 @@ export const GlobalStyles = createGlobalStyle`
 
 const GridContainer = styled.div`
     display: grid;
-    grid-template: repeat(3, 33.33333%) / repeat(3, 33.33333%);
+    ${({ size }) => `grid-template: repeat(${size}, ${100 / size}%) / repeat(${size}, ${100 / size}%);`}
     grid-auto-flow: column;
     height: 100%;
 `;
@@ -65,8 +65,8 @@ const GridContainer = styled.div`
 const GridCell = styled.div`
     position: relative;
     border: solid black 0;
-    border-right-width: ${({ y, borderWidth = '1px' }) => y < 2 ? borderWidth : 0}
-    border-bottom-width: ${({ x, borderWidth = '1px' }) => x < 2 ? borderWidth : 0}
+    border-right-width: ${({ y, size, borderWidth = '1px' }) => y < size - 1 ? borderWidth : 0}
+    border-bottom-width: ${({ x, size, borderWidth = '1px' }) => x < size - 1 ? borderWidth : 0}
 `;
 
 function Grid(props) {
@@ -74,12 +74,13 @@ function Grid(props) {
         children = [],
         borderWidth,
     } = props;
+    const size = Math.round(Math.sqrt(children.length));
     return (
-        <GridContainer>
-            {grid3x3.map(({ x, y }, i) => {
+        <GridContainer size={size}>
+            {gridNxN(size).map(({ x, y }, i) => {
                 const child = children[i];
                 return (
-                    <GridCell key={i} x={x} y={y}
+                    <GridCell key={i} x={x} y={y} size={size}
                         borderWidth={borderWidth}
                         onClick={child.onClick}
                     >
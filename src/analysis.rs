@@ -0,0 +1,571 @@
+//! Game tree search and move analysis.
+//!
+//! The search is a plain negamax, so its scores are only meaningful in
+//! games of two players. Threats, the squares where a player would complete
+//! a line, are found with the same line checks that decide the game.
+
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{Coord, DrawnBoardRule, Game, Misere, FullCoord, GameOverResult, GameState, OuterSquare, Piece, RuleSet, Square};
+use super::{FilledSquare, completes_line, lines};
+
+/// Score of a won game, minus the number of plies it takes to get there.
+pub const WIN_SCORE: i32 = 1_000_000;
+
+const INFINITY: i32 = 10_000_000;
+
+// Any score beyond this can only come from a game that was actually won.
+const WIN_THRESHOLD: i32 = WIN_SCORE - 1_000;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SearchLimits {
+    /// How many plies to look ahead.
+    pub depth: u32,
+    /// How many positions to visit before falling back to the heuristic.
+    /// `analyze` shares them out equally between the moves it searches.
+    pub nodes: u64,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            depth: 4,
+            nodes: 2_000_000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MoveLabel {
+    Winning,
+    Losing,
+    Drawing,
+    Unknown,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveAnalysis {
+    pub full_coord: FullCoord,
+    /// From the point of view of the player making the move.
+    pub score: i32,
+    /// The expected continuation, starting with `full_coord` itself.
+    pub principal_variation: Vec<FullCoord>,
+    pub label: MoveLabel,
+    /// How many positions were visited searching the move.
+    pub nodes: u64,
+}
+
+struct SearchResult {
+    score: i32,
+    // Whether the outcome (win, loss or draw) was proven rather than estimated
+    proven: bool,
+    principal_variation: Vec<FullCoord>,
+}
+
+struct Search<'a> {
+    limits: SearchLimits,
+    nodes: u64,
+    stop: Option<&'a AtomicBool>,
+}
+
+// Whether playing `full_coord` may have left `game` a dead draw. That takes
+// completing or killing the inner board it was played in, so the search
+// only runs the full check of `Game::is_dead_draw` after such moves.
+fn may_be_dead_draw(game: &Game, full_coord: &FullCoord) -> bool {
+    let outer = full_coord.outer();
+    game.get_outer_square(&outer) != OuterSquare::Incomplete || game.is_dead_board(&outer)
+}
+
+impl<'a> Search<'a> {
+    fn is_stopped(&self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    // `check_dead_draw` is set unless the game is known not to be a dead draw
+    fn negamax(&mut self, game: &Game, depth: u32, mut alpha: i32, beta: i32, ply: i32, check_dead_draw: bool) -> SearchResult {
+        self.nodes += 1;
+
+        match game.state() {
+            GameState::GameOver(GameOverResult::Draw) | GameState::AgreedDraw =>
+                return SearchResult { score: 0, proven: true, principal_variation: vec![] },
+            // Under misère rules or a tiebreak the winner didn't necessarily make the last move.
+            GameState::GameOver(GameOverResult::Winner(winner, _)) => {
+                let score = if winner == game.turn() { WIN_SCORE - ply } else { ply - WIN_SCORE };
+                return SearchResult { score, proven: true, principal_variation: vec![] };
+            },
+            GameState::InProgress => (),
+        }
+
+        if check_dead_draw && game.is_dead_draw() {
+            return SearchResult { score: 0, proven: true, principal_variation: vec![] };
+        }
+
+        if depth == 0 || self.nodes >= self.limits.nodes || self.is_stopped() {
+            return SearchResult { score: evaluate(game), proven: false, principal_variation: vec![] };
+        }
+
+        let mut best = SearchResult { score: -INFINITY, proven: true, principal_variation: vec![] };
+        for full_coord in game.moves() {
+            let mut child = game.clone();
+            child.play_move(&full_coord).unwrap();
+
+            let check_dead_draw = may_be_dead_draw(&child, &full_coord);
+            let result = self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, check_dead_draw);
+            let score = -result.score;
+
+            best.proven = best.proven && result.proven;
+            if score > best.score {
+                best.score = score;
+                best.principal_variation = vec![full_coord];
+                best.principal_variation.extend(result.principal_variation);
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                // A cut off leaves the remaining moves unexplored,
+                // which only matters if this isn't already a proven win.
+                best.proven = result.proven && score > WIN_THRESHOLD;
+                break;
+            }
+        }
+        best
+    }
+}
+
+/// Searches every legal move of `game`, in the order of `Game::get_moves`.
+pub fn analyze(game: &Game, limits: &SearchLimits) -> Vec<MoveAnalysis> {
+    // Without a flag to stop it the search always finishes
+    analyze_moves(game, limits, None).unwrap()
+}
+
+/// Like `analyze`, but gives up with `None` as soon as `stop` is set.
+pub fn analyze_until_stopped(game: &Game, limits: &SearchLimits, stop: &AtomicBool) -> Option<Vec<MoveAnalysis>> {
+    analyze_moves(game, limits, Some(stop))
+}
+
+fn analyze_moves(game: &Game, limits: &SearchLimits, stop: Option<&AtomicBool>) -> Option<Vec<MoveAnalysis>> {
+    let depth = if limits.depth > 0 { limits.depth - 1 } else { 0 };
+    let moves = game.get_moves();
+
+    // Every move gets its own share of the budget, so that the first moves
+    // can't use it up and leave the others to the heuristic alone.
+    let nodes = cmp::max(1, limits.nodes / cmp::max(1, moves.len() as u64));
+    let dead_draw = game.is_dead_draw();
+
+    let mut analyses = Vec::with_capacity(moves.len());
+    for full_coord in moves {
+        let mut child = game.clone();
+        child.play_move(&full_coord).unwrap();
+
+        let mut search = Search { limits: SearchLimits { nodes, ..*limits }, nodes: 0, stop };
+        let check_dead_draw = dead_draw || may_be_dead_draw(&child, &full_coord);
+        let result = search.negamax(&child, depth, -INFINITY, INFINITY, 1, check_dead_draw);
+        if search.is_stopped() {
+            return None;
+        }
+        let score = -result.score;
+
+        let mut principal_variation = vec![full_coord];
+        principal_variation.extend(result.principal_variation);
+
+        let label = if score > WIN_THRESHOLD {
+            MoveLabel::Winning
+        } else if score < -WIN_THRESHOLD {
+            MoveLabel::Losing
+        } else if result.proven {
+            MoveLabel::Drawing
+        } else {
+            MoveLabel::Unknown
+        };
+
+        analyses.push(MoveAnalysis {
+            full_coord,
+            score,
+            principal_variation,
+            label,
+            nodes: search.nodes,
+        });
+    }
+    Some(analyses)
+}
+
+/// The move with the highest score, or `None` if the game is over.
+pub fn best_move(game: &Game, limits: &SearchLimits) -> Option<MoveAnalysis> {
+    analyze(game, limits).into_iter().max_by_key(|analysis| analysis.score)
+}
+
+/// Scores swapping sides under the pie rule, from the point of view of the
+/// player who swaps, or `None` if `game` doesn't allow it.
+///
+/// After a swap the opponent moves next with the same pieces, so swapping is
+/// worth exactly as much as the best reply is worth to them. Returns that
+/// reply as well.
+pub fn analyze_swap(game: &Game, limits: &SearchLimits) -> Option<(i32, MoveAnalysis)> {
+    if !game.can_swap() {
+        return None;
+    }
+    best_move(game, limits).map(|reply| (-reply.score, reply))
+}
+
+/// Whether the side to move is better off swapping sides than replying.
+pub fn should_swap(game: &Game, limits: &SearchLimits) -> bool {
+    match analyze_swap(game, limits) {
+        Some((score, reply)) => score > reply.score,
+        None => false,
+    }
+}
+
+fn line_potential<T, F>(board: &[T], rules: &RuleSet, piece: Piece, is_blocked: F) -> i32
+    where T: FilledSquare, F: Fn(&T) -> bool
+{
+    let mut potential = 0;
+    for line in lines(rules.size, rules.win_length) {
+        let line = || line.coords().map(|coord| &board[coord.x * rules.size + coord.y]);
+        if line().any(&is_blocked) {
+            continue;
+        }
+
+        let ours = line().filter(|square| square.filling_piece() == Some(piece)).count() as i32;
+        let theirs = line().filter(|square| matches!(square.filling_piece(), Some(other) if other != piece)).count() as i32;
+        if theirs == 0 {
+            potential += ours * ours;
+        } else if ours == 0 {
+            potential -= theirs * theirs;
+        }
+    }
+    potential
+}
+
+/// A heuristic score of `game` from the point of view of the side to move.
+pub fn evaluate(game: &Game) -> i32 {
+    let piece = game.turn();
+    let mut score = 0;
+
+    // Under misère rules lines are something to avoid, and on the outer
+    // board so is every inner board that could become part of one.
+    let rules = game.rules();
+    let outer_sign = if rules.misere == Misere::Off { 1 } else { -1 };
+    let inner_sign = if rules.misere == Misere::AllBoards { -1 } else { 1 };
+
+    let size = game.size();
+    for x in 0..size {
+        for y in 0..size {
+            let outer = Coord::new(x, y);
+            match game.get_outer_square(&outer) {
+                OuterSquare::Complete(GameOverResult::Winner(winner, _)) => {
+                    let is_center = 2 * x + 1 == size && 2 * y + 1 == size;
+                    let value = outer_sign * if is_center { 150 } else { 100 };
+                    score += if winner == piece { value } else { -value };
+                },
+                OuterSquare::Complete(_) => (),
+                OuterSquare::Incomplete => {
+                    score += inner_sign * line_potential(game.inner_board(&outer), &rules, piece, |_: &Square| false);
+                },
+            }
+        }
+    }
+
+    // Draws block lines on the outer board for both players, unless the rules count them for both.
+    let blocks = rules.drawn_boards == DrawnBoardRule::CountsForNobody;
+    let is_draw = |square: &OuterSquare| blocks && *square == OuterSquare::Complete(GameOverResult::Draw);
+    score + outer_sign * 20 * line_potential(&game.outer_board, &rules, piece, is_draw)
+}
+
+/// Where a player could complete a line on one board, see `inner_threats` and `outer_threats`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Threats {
+    /// The open squares that complete a line when taken.
+    pub squares: Vec<Coord>,
+    /// The open squares that aren't threats themselves but set up at least two new ones when taken.
+    pub forks: Vec<Coord>,
+}
+
+impl Threats {
+    /// Whether there are two squares that complete a line, so blocking one isn't enough.
+    pub fn is_double(&self) -> bool {
+        self.squares.len() >= 2
+    }
+}
+
+// The threats of `piece` on a board, among the squares that `is_open` to it.
+fn board_threats<T, F>(board: &[T], rules: &RuleSet, piece: Piece, is_open: F) -> Threats
+    where T: FilledSquare, F: Fn(&Coord) -> bool
+{
+    let size = rules.size;
+    let open: Vec<_> = (0..size)
+        .flat_map(|x| (0..size).map(move |y| Coord::new(x, y)))
+        .filter(|coord| !board[coord.x * size + coord.y].is_filled() && is_open(coord))
+        .collect();
+    // Only lines through `through` count, so that threats which are already
+    // there don't make every square look like it sets one up
+    let completes = |through: &Coord, taken: &[Coord]| lines(size, rules.win_length)
+        .any(|line| line.contains(through) && completes_line(board, rules, piece, line, taken));
+
+    let mut threats = Threats::default();
+    for coord in &open {
+        if completes(coord, &[*coord]) {
+            threats.squares.push(*coord);
+            continue;
+        }
+
+        let follow_ups = open.iter()
+            .filter(|&other| other != coord && completes(coord, &[*coord, *other]))
+            .count();
+        if follow_ups >= 2 {
+            threats.forks.push(*coord);
+        }
+    }
+    threats
+}
+
+/// The threats of `piece` on the inner board at `outer`, none if the board is already complete.
+///
+/// Under `Misere::AllBoards` completing a line gives the board to the opponent,
+/// so these are squares to avoid rather than to aim for.
+pub fn inner_threats(game: &Game, outer: &Coord, piece: Piece) -> Threats {
+    if game.get_outer_square(outer) != OuterSquare::Incomplete {
+        return Threats::default();
+    }
+    board_threats(game.inner_board(outer), &game.rules, piece, |_| true)
+}
+
+/// The inner boards that complete a line on the outer board when `piece` wins them.
+///
+/// Only boards that `piece` can still win are considered. Completing an outer
+/// line wins the game with `OuterLineRule::FirstLineWins`, unless misère rules
+/// make it lose instead.
+pub fn outer_threats(game: &Game, piece: Piece) -> Threats {
+    board_threats(&game.outer_board, &game.rules, piece, |outer| game.can_take_board(outer, piece))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {PieRule, RuleSet};
+
+    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
+        FullCoord::try_new(outer, inner).unwrap()
+    }
+
+    fn game_with_moves(moves: Vec<((usize, usize), (usize, usize))>) -> Game {
+        game_with_rules_and_moves(RuleSet::default(), moves)
+    }
+
+    fn game_with_rules_and_moves(rules: RuleSet, moves: Vec<((usize, usize), (usize, usize))>) -> Game {
+        let mut game = Game::new_with_rules(rules);
+        for (outer, inner) in moves {
+            game.play_move(&fc(outer, inner)).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn analyze_should_return_every_legal_move() {
+        let game = game_with_moves(vec![((0, 0), (1, 1))]);
+        let limits = SearchLimits { depth: 2, ..SearchLimits::default() };
+
+        let analyses = analyze(&game, &limits);
+        let moves: Vec<_> = analyses.iter().map(|analysis| analysis.full_coord).collect();
+
+        assert_eq!(moves, game.get_moves());
+        for analysis in analyses {
+            assert_eq!(analysis.principal_variation[0], analysis.full_coord);
+        }
+    }
+
+    #[test]
+    fn analyze_should_label_a_game_winning_move_as_winning() {
+        // X owns the outer squares (0, 1) and (1, 1) and can take (2, 1)
+        // by playing its inner square (2, 0).
+        let game = game_with_moves(vec![
+            ((1, 1), (1, 0)),
+            ((1, 0), (1, 1)),
+            ((1, 1), (1, 2)),
+            ((1, 2), (1, 1)),
+            ((1, 1), (1, 1)),
+            ((1, 0), (0, 1)),
+            ((0, 1), (1, 1)),
+            ((0, 0), (0, 1)),
+            ((0, 1), (0, 1)),
+            ((0, 1), (2, 0)),
+            ((2, 0), (1, 1)),
+            ((2, 0), (0, 1)),
+            ((0, 1), (2, 1)),
+            ((2, 1), (0, 1)),
+            ((2, 1), (2, 1)),
+            ((2, 1), (0, 2)),
+            ((0, 2), (0, 1)),
+            ((1, 2), (2, 1)),
+            ((2, 1), (2, 2)),
+            ((2, 2), (2, 1)),
+        ]);
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let analyses = analyze(&game, &limits);
+        let winning = analyses.iter()
+            .find(|analysis| analysis.full_coord == fc((2, 1), (2, 0)))
+            .unwrap();
+
+        assert_eq!(winning.label, MoveLabel::Winning);
+        assert_eq!(winning.score, WIN_SCORE - 1);
+        assert_eq!(best_move(&game, &limits).unwrap().full_coord, fc((2, 1), (2, 0)));
+    }
+
+    // The moves of analyze_should_label_a_game_winning_move_as_winning, after
+    // which X can take the outer square (2, 1), completing a line, by playing (2, 0).
+    fn winning_game_moves() -> Vec<((usize, usize), (usize, usize))> {
+        vec![
+            ((1, 1), (1, 0)),
+            ((1, 0), (1, 1)),
+            ((1, 1), (1, 2)),
+            ((1, 2), (1, 1)),
+            ((1, 1), (1, 1)),
+            ((1, 0), (0, 1)),
+            ((0, 1), (1, 1)),
+            ((0, 0), (0, 1)),
+            ((0, 1), (0, 1)),
+            ((0, 1), (2, 0)),
+            ((2, 0), (1, 1)),
+            ((2, 0), (0, 1)),
+            ((0, 1), (2, 1)),
+            ((2, 1), (0, 1)),
+            ((2, 1), (2, 1)),
+            ((2, 1), (0, 2)),
+            ((0, 2), (0, 1)),
+            ((1, 2), (2, 1)),
+            ((2, 1), (2, 2)),
+            ((2, 2), (2, 1)),
+        ]
+    }
+
+    #[test]
+    fn under_misere_rules_completing_the_outer_line_should_be_labelled_losing() {
+        let rules = RuleSet { misere: Misere::OuterBoard, ..RuleSet::default() };
+        let game = game_with_rules_and_moves(rules, winning_game_moves());
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let analyses = analyze(&game, &limits);
+        let losing = analyses.iter()
+            .find(|analysis| analysis.full_coord == fc((2, 1), (2, 0)))
+            .unwrap();
+
+        assert_eq!(losing.label, MoveLabel::Losing);
+        assert!(best_move(&game, &limits).unwrap().full_coord != fc((2, 1), (2, 0)));
+    }
+
+    #[test]
+    fn the_engine_should_only_swap_when_the_first_move_favours_x() {
+        let rules = RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..RuleSet::default() };
+        let limits = SearchLimits { depth: 2, ..SearchLimits::default() };
+
+        for &(outer, inner) in &[((1, 1), (1, 1)), ((0, 0), (0, 0))] {
+            let game = game_with_rules_and_moves(rules, vec![(outer, inner)]);
+            let (score, reply) = analyze_swap(&game, &limits).unwrap();
+
+            assert_eq!(score, -reply.score);
+            assert_eq!(should_swap(&game, &limits), reply.score < 0);
+        }
+
+        assert_eq!(analyze_swap(&Game::new_with_rules(rules), &limits), None);
+    }
+
+    #[test]
+    fn analyze_should_label_unresolved_moves_as_unknown() {
+        let game = Game::new();
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        for analysis in analyze(&game, &limits) {
+            assert_eq!(analysis.label, MoveLabel::Unknown);
+        }
+    }
+
+    #[test]
+    fn a_small_node_budget_should_still_search_every_move() {
+        let game = Game::new();
+        let limits = SearchLimits { depth: 3, nodes: 81 * 10 };
+
+        // A move that ran out of nodes straight away would be scored by the
+        // heuristic alone, without any continuation
+        for analysis in analyze(&game, &limits) {
+            assert!(analysis.principal_variation.len() > 1, "{} wasn't searched", analysis.full_coord);
+        }
+    }
+
+    #[test]
+    fn a_stopped_search_should_give_up() {
+        let game = Game::new();
+        let limits = SearchLimits { depth: 2, ..SearchLimits::default() };
+
+        let analyses = analyze_until_stopped(&game, &limits, &AtomicBool::new(false)).unwrap();
+        assert_eq!(analyses, analyze(&game, &limits));
+        assert!(analyses.iter().all(|analysis| analysis.nodes > 0));
+        assert_eq!(analyze_until_stopped(&game, &limits, &AtomicBool::new(true)), None);
+    }
+
+    #[test]
+    fn a_move_that_leaves_the_game_a_dead_draw_should_be_labelled_drawing() {
+        // X needs the last inner board for a line, and O can leave it
+        // without a line for anyone by blocking at (2, 2)
+        let position = "xxxoooxxx/........./........./ooooooxxx/........./........./xxxxxxxox/......ox./......ox. o 2,2";
+        let game = Game::from_position(position).unwrap();
+        assert!(!game.is_dead_draw());
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let analyses = analyze(&game, &limits);
+        let blocking = analyses.iter()
+            .find(|analysis| analysis.full_coord == fc((2, 2), (2, 2)))
+            .unwrap();
+
+        assert_eq!(blocking.label, MoveLabel::Drawing);
+        assert_eq!(blocking.score, 0);
+    }
+
+    fn ct(x: usize, y: usize) -> Coord {
+        Coord::try_new(x, y).unwrap()
+    }
+
+    #[test]
+    fn the_last_square_of_a_line_should_be_a_threat_on_both_boards() {
+        let game = game_with_moves(winning_game_moves());
+
+        assert_eq!(inner_threats(&game, &ct(2, 1), Piece::X).squares, vec![ct(2, 0)]);
+        assert!(outer_threats(&game, Piece::X).squares.contains(&ct(2, 1)));
+        assert!(!outer_threats(&game, Piece::O).squares.contains(&ct(2, 1)));
+
+        // Completed boards have nothing left to threaten
+        assert_eq!(inner_threats(&game, &ct(1, 1), Piece::O), Threats::default());
+    }
+
+    #[test]
+    fn a_square_leaving_two_threats_should_be_a_fork() {
+        // X holds opposite corners of (0, 0) around O in the center,
+        // so the other two corners each set up two lines at once
+        let game = Game::from_position(
+            "x..o...../.o......./..x....../........./........./\
+             ........./........./........./......... x 0,0",
+        ).unwrap();
+
+        let threats = inner_threats(&game, &ct(0, 0), Piece::X);
+        assert!(threats.squares.is_empty());
+        assert!(!threats.is_double());
+        assert_eq!(threats.forks, vec![ct(0, 2), ct(2, 0)]);
+        assert!(!inner_threats(&game, &ct(0, 0), Piece::O).forks.contains(&ct(0, 2)));
+    }
+
+    #[test]
+    fn threats_already_on_the_board_should_not_make_a_fork() {
+        // X already threatens (2, 0) and (0, 2) of (0, 0). Only the center
+        // sets up two more lines, every other open square at most one
+        let game = Game::from_position(
+            "xx......./x......../........./....o..../........./\
+             ........./........./o......../........o x 0,0",
+        ).unwrap();
+
+        let threats = inner_threats(&game, &ct(0, 0), Piece::X);
+        assert_eq!(threats.squares, vec![ct(0, 2), ct(2, 0)]);
+        assert_eq!(threats.forks, vec![ct(1, 1)]);
+    }
+}
@@ -0,0 +1,327 @@
+//! Replays finished games and marks the moves that gave away the most.
+
+use std::fmt;
+
+use super::analysis::{self, MoveLabel, SearchLimits};
+use super::{Action, Game, FullCoord, GameOverResult, GameState, Piece, RuleSet, WinReason};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Mark {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Mark {
+    fn from_analysis(loss: i32, best: MoveLabel, played: MoveLabel) -> Option<Self> {
+        // Throwing away a forced win, or walking into a forced loss,
+        // is always a blunder no matter what the scores say.
+        if (best == MoveLabel::Winning && played != MoveLabel::Winning)
+            || (played == MoveLabel::Losing && best != MoveLabel::Losing) {
+            return Some(Mark::Blunder);
+        }
+
+        if loss >= 300 {
+            Some(Mark::Blunder)
+        } else if loss >= 100 {
+            Some(Mark::Mistake)
+        } else if loss >= 40 {
+            Some(Mark::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Mark::Inaccuracy => "?!",
+            Mark::Mistake => "?",
+            Mark::Blunder => "??",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Annotation {
+    pub mark: Mark,
+    /// How much worse the played move scored than the best one.
+    pub loss: i32,
+    pub better: Action,
+    /// The opponent's best continuation after the played move.
+    pub refutation: Vec<FullCoord>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnnotatedMove {
+    pub action: Action,
+    pub piece: Piece,
+    pub score: i32,
+    pub annotation: Option<Annotation>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnnotatedGame {
+    /// The position the game started from, if it was set up rather than
+    /// started from an empty board, see `Game::from_position`.
+    pub setup: Option<String>,
+    pub moves: Vec<AnnotatedMove>,
+    pub state: GameState,
+}
+
+fn write_line(f: &mut fmt::Formatter, line: &[FullCoord]) -> fmt::Result {
+    for (i, full_coord) in line.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", full_coord)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for AnnotatedGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref setup) = self.setup {
+            writeln!(f, "Setup {}", setup)?;
+        }
+
+        for (i, annotated) in self.moves.iter().enumerate() {
+            write!(f, "{}. {:?} {}", i + 1, annotated.piece, annotated.action)?;
+
+            if let Some(ref annotation) = annotated.annotation {
+                write!(f, " {} {:?} (-{}), better {}",
+                       annotation.mark.symbol(), annotation.mark, annotation.loss, annotation.better)?;
+                if !annotation.refutation.is_empty() {
+                    write!(f, ", punished by ")?;
+                    write_line(f, &annotation.refutation)?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        match &self.state {
+            GameState::InProgress => writeln!(f, "Unfinished"),
+            GameState::GameOver(GameOverResult::Draw) => writeln!(f, "Draw"),
+            GameState::AgreedDraw => writeln!(f, "Draw agreed"),
+            GameState::GameOver(GameOverResult::Winner(piece, reason)) => {
+                write!(f, "{:?} wins", piece)?;
+                // Only games of two players can be resigned or forfeited
+                match (reason, piece.opposite()) {
+                    (WinReason::Resignation, Some(loser)) => write!(f, ", {:?} resigned", loser)?,
+                    (WinReason::Forfeit(reason), Some(loser)) => write!(f, ", {:?} forfeited ({:?})", loser, reason)?,
+                    _ => (),
+                }
+                writeln!(f)
+            },
+        }
+    }
+}
+
+// An action the side to move could take, as seen by the search
+struct Candidate {
+    action: Action,
+    score: i32,
+    label: MoveLabel,
+    refutation: Vec<FullCoord>,
+}
+
+fn candidates(game: &Game, limits: &SearchLimits) -> Vec<Candidate> {
+    let analyses = analysis::analyze(game, limits);
+
+    // A swap hands the move to the opponent, so it's worth the reverse of their best reply
+    let swap = if game.can_swap() {
+        analyses.iter().max_by_key(|analysis| analysis.score).map(|reply| Candidate {
+            action: Action::Swap,
+            score: -reply.score,
+            label: match reply.label {
+                MoveLabel::Winning => MoveLabel::Losing,
+                MoveLabel::Losing => MoveLabel::Winning,
+                label => label,
+            },
+            refutation: reply.principal_variation.clone(),
+        })
+    } else {
+        None
+    };
+
+    analyses.into_iter().map(|analysis| Candidate {
+        action: Action::Move(analysis.full_coord),
+        score: analysis.score,
+        label: analysis.label,
+        refutation: analysis.principal_variation[1..].to_vec(),
+    }).chain(swap).collect()
+}
+
+/// Replays `actions` from `Game::new()`, searching every position on the way.
+pub fn annotate(actions: &[Action], limits: &SearchLimits) -> Result<AnnotatedGame, String> {
+    annotate_with_rules(RuleSet::default(), actions, limits)
+}
+
+pub fn annotate_with_rules(rules: RuleSet, actions: &[Action], limits: &SearchLimits)
+    -> Result<AnnotatedGame, String>
+{
+    annotate_game(Game::new_with_rules(rules), None, actions, limits)
+}
+
+/// Like `annotate_with_rules`, but replays `actions` from a position set up
+/// with `Game::from_position_with_rules`, which is kept in the record.
+pub fn annotate_from_position(rules: RuleSet, position: &str, actions: &[Action], limits: &SearchLimits)
+    -> Result<AnnotatedGame, String>
+{
+    let game = Game::from_position_with_rules(rules, position)?;
+    let setup = game.position_string();
+    annotate_game(game, Some(setup), actions, limits)
+}
+
+fn annotate_game(mut game: Game, setup: Option<String>, actions: &[Action], limits: &SearchLimits)
+    -> Result<AnnotatedGame, String>
+{
+    let mut annotated_moves = Vec::with_capacity(actions.len());
+
+    for action in actions {
+        let candidates = candidates(&game, limits);
+        let played = candidates.iter()
+            .find(|candidate| candidate.action == *action)
+            .ok_or_else(|| format!("Illegal move {} at ply {}", action, annotated_moves.len() + 1))?;
+        let best = candidates.iter().max_by_key(|candidate| candidate.score).unwrap();
+
+        let loss = best.score - played.score;
+        let annotation = Mark::from_analysis(loss, best.label, played.label).map(|mark| Annotation {
+            mark,
+            loss,
+            better: best.action,
+            refutation: played.refutation.clone(),
+        });
+
+        annotated_moves.push(AnnotatedMove {
+            action: *action,
+            piece: game.turn(),
+            score: played.score,
+            annotation,
+        });
+
+        game.play_action(action)?;
+    }
+
+    Ok(AnnotatedGame {
+        setup,
+        moves: annotated_moves,
+        state: game.state(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use PieRule;
+
+    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
+        FullCoord::try_new(outer, inner).unwrap()
+    }
+
+    fn moves(full_coords: Vec<FullCoord>) -> Vec<Action> {
+        full_coords.into_iter().map(Action::Move).collect()
+    }
+
+    // X can win the game by playing (2, 1) (2, 0) after these moves.
+    fn moves_before_win() -> Vec<FullCoord> {
+        vec![
+            fc((1, 1), (1, 0)),
+            fc((1, 0), (1, 1)),
+            fc((1, 1), (1, 2)),
+            fc((1, 2), (1, 1)),
+            fc((1, 1), (1, 1)),
+            fc((1, 0), (0, 1)),
+            fc((0, 1), (1, 1)),
+            fc((0, 0), (0, 1)),
+            fc((0, 1), (0, 1)),
+            fc((0, 1), (2, 0)),
+            fc((2, 0), (1, 1)),
+            fc((2, 0), (0, 1)),
+            fc((0, 1), (2, 1)),
+            fc((2, 1), (0, 1)),
+            fc((2, 1), (2, 1)),
+            fc((2, 1), (0, 2)),
+            fc((0, 2), (0, 1)),
+            fc((1, 2), (2, 1)),
+            fc((2, 1), (2, 2)),
+            fc((2, 2), (2, 1)),
+        ]
+    }
+
+    #[test]
+    fn missing_a_winning_move_should_be_marked_as_a_blunder() {
+        let mut full_coords = moves_before_win();
+        full_coords.push(fc((2, 1), (1, 1)));
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let game = annotate(&moves(full_coords), &limits).unwrap();
+        let last = game.moves.last().unwrap();
+
+        assert_eq!(last.piece, Piece::X);
+        let annotation = last.annotation.clone().unwrap();
+        assert_eq!(annotation.mark, Mark::Blunder);
+        assert_eq!(annotation.better, Action::Move(fc((2, 1), (2, 0))));
+    }
+
+    #[test]
+    fn playing_the_winning_move_should_not_be_marked() {
+        let mut full_coords = moves_before_win();
+        full_coords.push(fc((2, 1), (2, 0)));
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let game = annotate(&moves(full_coords), &limits).unwrap();
+
+        assert_eq!(game.moves.last().unwrap().annotation, None);
+        assert!(game.to_string().ends_with("21. X 2,1:2,0\nX wins\n"));
+    }
+
+    #[test]
+    fn an_illegal_move_should_be_reported() {
+        let full_coords = vec![fc((0, 0), (1, 1)), fc((0, 0), (0, 0))];
+
+        let result = annotate(&moves(full_coords), &SearchLimits::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn a_swap_should_be_recorded_and_annotated() {
+        let rules = RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..RuleSet::default() };
+        let actions = vec![Action::Move(fc((1, 1), (1, 1))), Action::Swap, Action::Move(fc((1, 1), (0, 0)))];
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let game = annotate_with_rules(rules, &actions, &limits).unwrap();
+
+        assert_eq!(game.moves[1].action, Action::Swap);
+        assert_eq!(game.moves[1].piece, Piece::O);
+        assert_eq!(game.moves[2].piece, Piece::O);
+        assert!(game.to_string().starts_with("1. X 1,1:1,1\n2. O swap"));
+        assert!(annotate(&actions, &limits).is_err());
+    }
+
+    #[test]
+    fn the_record_should_report_how_the_game_ended() {
+        let mut game = AnnotatedGame { setup: None, moves: vec![], state: GameState::AgreedDraw };
+        assert_eq!(game.to_string(), "Draw agreed\n");
+
+        game.state = GameState::GameOver(GameOverResult::Winner(Piece::X, WinReason::Resignation));
+        assert_eq!(game.to_string(), "X wins, O resigned\n");
+    }
+
+    #[test]
+    fn a_game_from_a_set_up_position_should_record_the_setup() {
+        let mut start = Game::new();
+        for full_coord in moves_before_win() {
+            start.play_move(&full_coord).unwrap();
+        }
+        let position = start.position_string();
+        let limits = SearchLimits { depth: 1, ..SearchLimits::default() };
+
+        let game = annotate_from_position(RuleSet::default(), &position, &moves(vec![fc((2, 1), (2, 0))]), &limits)
+            .unwrap();
+
+        assert_eq!(game.setup, Some(position.clone()));
+        assert_eq!(game.to_string(), format!("Setup {}\n1. X 2,1:2,0\nX wins\n", position));
+    }
+}
@@ -0,0 +1,39 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::io::{self, Read};
+
+use ultimate_tic_tac_toe::{Action, PieRule, RuleSet};
+use ultimate_tic_tac_toe::analysis::SearchLimits;
+use ultimate_tic_tac_toe::annotate::{annotate_from_position, annotate_with_rules};
+
+fn main() {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).unwrap();
+
+    let mut rules = RuleSet::default();
+    let mut setup = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--pie-rule" => rules.pie_rule = PieRule::SwapAfterFirstMove,
+            // A position as written by `Game::position_string`, quoted as one argument
+            "--setup" => setup = args.next(),
+            _ => (),
+        }
+    }
+
+    let limits = SearchLimits::default();
+    let actions: Result<Vec<Action>, String> = input.split_whitespace().map(str::parse).collect();
+    let result = actions.and_then(|actions| match setup {
+        Some(ref position) => annotate_from_position(rules, position, &actions, &limits),
+        None => annotate_with_rules(rules, &actions, &limits),
+    });
+
+    match result {
+        Ok(game) => print!("{}", game),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    }
+}
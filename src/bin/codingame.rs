@@ -0,0 +1,44 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::io::{self, Write};
+
+use ultimate_tic_tac_toe::Game;
+use ultimate_tic_tac_toe::analysis::{self, SearchLimits};
+use ultimate_tic_tac_toe::codingame::Adapter;
+
+// CodinGame allows 100ms per turn, the node budget keeps the search well within it
+const LIMITS: SearchLimits = SearchLimits { depth: 6, nodes: 10_000 };
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+
+    // Tournaments start bots from an opening, written by `Game::position_string`
+    let mut args = std::env::args().skip(1);
+    let mut adapter = match (args.next(), args.next()) {
+        (Some(ref flag), Some(ref position)) if flag == "--setup" => match Game::from_position(position) {
+            Ok(game) => Adapter::from_game(game),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            },
+        },
+        _ => Adapter::new(),
+    };
+
+    loop {
+        match adapter.turn(&mut input, |game| analysis::best_move(game, &LIMITS).unwrap().full_coord) {
+            Ok(Some(reply)) => {
+                let mut output = stdout.lock();
+                writeln!(output, "{}", reply).unwrap();
+                output.flush().unwrap();
+            },
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            },
+        }
+    }
+}
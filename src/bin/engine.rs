@@ -0,0 +1,12 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::io::{self, BufReader};
+
+use ultimate_tic_tac_toe::engine;
+
+fn main() {
+    if let Err(err) = engine::run(BufReader::new(io::stdin()), io::stdout()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,29 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::fs;
+
+use ultimate_tic_tac_toe::openings::{generate, OpeningSettings};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: openings <suite file> [count]");
+            std::process::exit(1);
+        },
+    };
+
+    let mut settings = OpeningSettings::default();
+    if let Some(count) = std::env::args().nth(2) {
+        settings.count = count.parse().unwrap_or_else(|_| {
+            eprintln!("The count needs to be a number");
+            std::process::exit(1);
+        });
+    }
+
+    let suite: String = generate(&settings).iter().map(|opening| format!("{}\n", opening)).collect();
+    if let Err(err) = fs::write(&path, suite) {
+        eprintln!("Could not write {:?}: {}", path, err);
+        std::process::exit(1);
+    }
+}
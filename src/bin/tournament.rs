@@ -0,0 +1,43 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::fs;
+use std::time::Duration;
+
+use ultimate_tic_tac_toe::Game;
+use ultimate_tic_tac_toe::openings::parse_suite;
+use ultimate_tic_tac_toe::player::{play_tournament, Player, ProcessPlayer};
+
+const MOVE_LIMIT: Duration = Duration::from_millis(100);
+const TOTAL_LIMIT: Duration = Duration::from_secs(10);
+
+// Bots get the opening as `--setup <position>`, like the `codingame` bot accepts it.
+fn bot(command: &str) -> impl FnMut(&Game) -> Result<Box<dyn Player>, String> + '_ {
+    move |game| {
+        let position = game.position_string();
+        let player = ProcessPlayer::spawn(command, &["--setup", &position], MOVE_LIMIT, TOTAL_LIMIT)?;
+        Ok(Box::new(player) as Box<dyn Player>)
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (suite, games, first, second) = match args {
+        [suite, games, first, second] => (suite, games, first, second),
+        _ => return Err("Usage: tournament <suite file> <games> <first bot> <second bot>".into()),
+    };
+
+    let text = fs::read_to_string(suite).map_err(|err| format!("Could not read {:?}: {}", suite, err))?;
+    let openings = parse_suite(&text)?;
+    let games = games.parse().map_err(|_| "The number of games needs to be a number".to_string())?;
+
+    let score = play_tournament(&openings, games, bot(first), bot(second))?;
+    println!("{} won {}, drew {} and lost {} against {}", first, score.wins, score.draws, score.losses, second);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Adapter for the CodinGame "Ultimate Tic-Tac-Toe" referee protocol.
+//!
+//! Every turn the referee sends the opponent's last move as `row col`
+//! (`-1 -1` if we are the first to play), then the number of valid actions
+//! followed by one `row col` line per action. The bot answers with a single
+//! `row col` line. All coordinates are global, in the range `0..SIZE * SIZE`,
+//! as the protocol only covers the standard board size.
+
+use std::io::BufRead;
+
+use super::{Game, FullCoord, GameState, SIZE};
+
+/// Converts a global CodinGame `(row, col)` pair into a `FullCoord`.
+pub fn from_row_col(row: usize, col: usize) -> Result<FullCoord, String> {
+    FullCoord::try_from_global((col, row), SIZE)
+}
+
+/// Converts a `FullCoord` into a global CodinGame `(row, col)` pair.
+pub fn to_row_col(full_coord: &FullCoord) -> (usize, usize) {
+    let (col, row) = full_coord.to_global(SIZE);
+    (row, col)
+}
+
+/// Parses a `row col` line, where `-1 -1` stands for "no move".
+pub fn parse_row_col(line: &str) -> Result<Option<FullCoord>, String> {
+    let mut parts = line.split_whitespace().map(|part| part.parse::<i64>());
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(-1)), Some(Ok(-1)), None) => Ok(None),
+        (Some(Ok(row)), Some(Ok(col)), None) if row >= 0 && col >= 0 =>
+            from_row_col(row as usize, col as usize).map(Some),
+        _ => Err(format!("Malformed coordinate line: {:?}", line)),
+    }
+}
+
+/// Formats the input the referee sends to the side to move.
+pub fn turn_input(game: &Game, last_move: Option<FullCoord>) -> String {
+    let (row, col) = match last_move {
+        Some(full_coord) => {
+            let (row, col) = to_row_col(&full_coord);
+            (row as i64, col as i64)
+        },
+        None => (-1, -1),
+    };
+
+    let moves = game.get_moves();
+    let mut input = format!("{} {}\n{}\n", row, col, moves.len());
+    for full_coord in moves {
+        let (row, col) = to_row_col(&full_coord);
+        input.push_str(&format!("{} {}\n", row, col));
+    }
+    input
+}
+
+fn read_line<R: BufRead>(input: &mut R) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line.trim().into())),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn expect_line<R: BufRead>(input: &mut R) -> Result<String, String> {
+    read_line(input)?.ok_or_else(|| "Unexpected end of input".into())
+}
+
+/// Keeps track of the game on the bot's side of the protocol.
+pub struct Adapter {
+    game: Game,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Self::from_game(Game::new())
+    }
+
+    /// Starts from a game already in progress, such as a tournament opening.
+    /// The referee only reports the moves played from there on.
+    pub fn from_game(game: Game) -> Self {
+        Self { game }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Reads one turn from `input`, asks `choose` for a move and plays it.
+    ///
+    /// Returns the reply line to send to the referee, or `None` once the
+    /// input is exhausted. The valid actions listed by the referee have to
+    /// agree with `Game::get_moves`, otherwise the two sides are out of sync.
+    pub fn turn<R, F>(&mut self, input: &mut R, choose: F) -> Result<Option<String>, String>
+        where R: BufRead, F: FnOnce(&Game) -> FullCoord
+    {
+        let opponent_move = match read_line(input)? {
+            Some(line) => parse_row_col(&line)?,
+            None => return Ok(None),
+        };
+        if let Some(full_coord) = opponent_move {
+            self.game.play_move(&full_coord)?;
+        }
+
+        let count_line = expect_line(input)?;
+        let count = count_line.parse::<usize>()
+            .map_err(|_| format!("Malformed action count: {:?}", count_line))?;
+
+        let mut actions = Vec::with_capacity(count);
+        for _ in 0..count {
+            match parse_row_col(&expect_line(input)?)? {
+                Some(full_coord) => actions.push(full_coord),
+                None => return Err("Unexpected -1 -1 in valid actions".into()),
+            }
+        }
+
+        // Compared as sorted lists, so that repeated actions don't count as other moves
+        let mut actions: Vec<_> = actions.iter().map(to_row_col).collect();
+        let mut moves: Vec<_> = self.game.moves().map(|full_coord| to_row_col(&full_coord)).collect();
+        actions.sort();
+        moves.sort();
+        if actions != moves {
+            return Err("Valid actions do not match the moves of the game".into());
+        }
+
+        let full_coord = choose(&self.game);
+        self.game.play_move(&full_coord)?;
+
+        let (row, col) = to_row_col(&full_coord);
+        Ok(Some(format!("{} {}", row, col)))
+    }
+}
+
+/// A local stand-in for the CodinGame referee.
+///
+/// It produces the text input for whichever side is to move and applies the
+/// replies, so bots can be tested without the website.
+pub struct Referee {
+    game: Game,
+    last_move: Option<FullCoord>,
+}
+
+impl Default for Referee {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Referee {
+    pub fn new() -> Self {
+        Self {
+            game: Game::new(),
+            last_move: None,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// The input for the side to move, or `None` once the game is over.
+    pub fn turn_input(&self) -> Option<String> {
+        match self.game.state() {
+            GameState::InProgress => Some(turn_input(&self.game, self.last_move)),
+            GameState::GameOver(_) | GameState::AgreedDraw => None,
+        }
+    }
+
+    /// Applies a reply line from the side to move.
+    pub fn apply_reply(&mut self, reply: &str) -> Result<FullCoord, String> {
+        let full_coord = parse_row_col(reply)?
+            .ok_or_else(|| "A move is required".to_string())?;
+        self.game.play_move(&full_coord)?;
+        self.last_move = Some(full_coord);
+        Ok(full_coord)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_move(game: &Game) -> FullCoord {
+        game.get_moves()[0]
+    }
+
+    fn last_move(game: &Game) -> FullCoord {
+        *game.get_moves().last().unwrap()
+    }
+
+    #[test]
+    fn row_col_conversion_should_round_trip() {
+        for row in 0..SIZE * SIZE {
+            for col in 0..SIZE * SIZE {
+                let full_coord = from_row_col(row, col).unwrap();
+                assert_eq!(to_row_col(&full_coord), (row, col));
+            }
+        }
+
+        let full_coord = from_row_col(4, 7).unwrap();
+        assert_eq!(full_coord, FullCoord::try_new((2, 1), (1, 1)).unwrap());
+    }
+
+    #[test]
+    fn two_adapters_should_play_a_full_game_through_the_referee() {
+        let mut referee = Referee::new();
+        let mut bots = [Adapter::new(), Adapter::new()];
+        let choosers: [fn(&Game) -> FullCoord; 2] = [first_move, last_move];
+
+        let mut side = 0;
+        while let Some(input) = referee.turn_input() {
+            let reply = bots[side]
+                .turn(&mut input.as_bytes(), choosers[side])
+                .unwrap()
+                .unwrap();
+            referee.apply_reply(&reply).unwrap();
+            side = 1 - side;
+        }
+
+        assert!(match referee.game().state() {
+            GameState::GameOver(_) => true,
+            GameState::InProgress | GameState::AgreedDraw => false,
+        });
+    }
+
+    #[test]
+    fn an_adapter_should_reject_valid_actions_that_disagree_with_the_game() {
+        let mut adapter = Adapter::new();
+        let input = "-1 -1\n2\n0 0\n4 4\n";
+
+        let result = adapter.turn(&mut input.as_bytes(), first_move);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn an_adapter_should_accept_valid_actions_in_any_order_but_not_repeated() {
+        let input = turn_input(&Game::new(), None);
+        let mut lines: Vec<&str> = input.lines().collect();
+        lines[2..].reverse();
+        let reversed = lines.join("\n") + "\n";
+        assert!(Adapter::new().turn(&mut reversed.as_bytes(), first_move).is_ok());
+
+        lines[3] = lines[2];
+        let repeated = lines.join("\n") + "\n";
+        assert!(Adapter::new().turn(&mut repeated.as_bytes(), first_move).is_err());
+    }
+
+    #[test]
+    fn an_adapter_should_stop_at_the_end_of_input() {
+        let mut adapter = Adapter::new();
+
+        assert_eq!(adapter.turn(&mut "".as_bytes(), first_move), Ok(None));
+    }
+}
@@ -0,0 +1,478 @@
+//! A line based protocol for driving the engine from GUIs and bot arenas,
+//! modelled on UCI.
+//!
+//! Commands, one per line:
+//!
+//! - `newgame` starts a new game.
+//! - `position startpos [moves <action>...]` sets up the game to search from
+//!   an empty board, and `position setup <position> [moves <action>...]` from
+//!   a position written by `Game::position_string`. Actions are written like
+//!   `Action`, for example `1,1:0,2` or `swap`.
+//! - `go [depth <plies>] [nodes <count>] [movetime <ms>] [infinite]` searches
+//!   the game, see `GoLimits`.
+//! - `stop` ends the search early.
+//! - `setoption name <name> value <value>` sets `Depth`, `Nodes` or `PieRule`.
+//! - `isready` is answered with `readyok` once the commands before it are done.
+//! - `quit` exits.
+//!
+//! The search writes `info depth <plies> score <score> nodes <count> time
+//! <ms> pv <move>...` for every depth it completes, where the score is
+//! `cp <score>` from the point of view of the side to move, or `win <plies>`
+//! and `loss <plies>` once the outcome is proven. It ends with `bestmove
+//! <action>`, or `bestmove none` if the game is over. Commands that can't be
+//! carried out are answered with `info string <error>`.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::analysis::{self, MoveLabel, SearchLimits, WIN_SCORE};
+use super::{Action, FullCoord, Game, PieRule, RuleSet, Square};
+
+/// What a `go` command asks for. Limits that aren't given fall back to the
+/// engine's options.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct GoLimits {
+    /// The deepest search to run. Without it a timed or infinite search
+    /// deepens until the board is full, and any other search stops at the
+    /// `Depth` option.
+    pub depth: Option<u32>,
+    /// The nodes every depth may visit, see `SearchLimits::nodes`.
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    /// Searches until `stop`, or until deeper searches can't see any further.
+    pub infinite: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    NewGame,
+    Position { setup: Option<String>, actions: Vec<Action> },
+    Go(GoLimits),
+    Stop,
+    SetOption { name: String, value: String },
+    IsReady,
+    Quit,
+}
+
+fn parse_number<T: FromStr>(name: &str, value: Option<&&str>) -> Result<T, String> {
+    value.and_then(|value| value.parse().ok()).ok_or_else(|| format!("Expected a number after {}", name))
+}
+
+fn parse_position(words: &[&str]) -> Result<Command, String> {
+    let (start, actions) = match words.iter().position(|&word| word == "moves") {
+        Some(i) => (&words[..i], &words[i + 1..]),
+        None => (words, &[][..]),
+    };
+
+    let setup = match start {
+        ["startpos"] => None,
+        ["setup", position @ ..] if !position.is_empty() => Some(position.join(" ")),
+        _ => return Err("Expected position startpos or position setup <position>".into()),
+    };
+    let actions = actions.iter().map(|action| action.parse()).collect::<Result<_, _>>()?;
+    Ok(Command::Position { setup, actions })
+}
+
+fn parse_go(words: &[&str]) -> Result<GoLimits, String> {
+    let mut limits = GoLimits::default();
+    let mut words = words.iter();
+    while let Some(&word) = words.next() {
+        match word {
+            "depth" => limits.depth = Some(parse_number(word, words.next())?),
+            "nodes" => limits.nodes = Some(parse_number(word, words.next())?),
+            "movetime" => limits.movetime = Some(Duration::from_millis(parse_number(word, words.next())?)),
+            "infinite" => limits.infinite = true,
+            _ => return Err(format!("Unknown search limit {:?}", word)),
+        }
+    }
+    Ok(limits)
+}
+
+fn parse_option(words: &[&str]) -> Result<Command, String> {
+    let value = words.iter().position(|&word| word == "value");
+    match (words.first(), value) {
+        (Some(&"name"), Some(i)) if i > 1 => Ok(Command::SetOption {
+            name: words[1..i].join(" "),
+            value: words[i + 1..].join(" "),
+        }),
+        _ => Err("Expected setoption name <name> value <value>".into()),
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.split_first() {
+            Some((&"newgame", [])) => Ok(Command::NewGame),
+            Some((&"position", rest)) => parse_position(rest),
+            Some((&"go", rest)) => parse_go(rest).map(Command::Go),
+            Some((&"stop", [])) => Ok(Command::Stop),
+            Some((&"setoption", rest)) => parse_option(rest),
+            Some((&"isready", [])) => Ok(Command::IsReady),
+            Some((&"quit", [])) => Ok(Command::Quit),
+            _ => Err(format!("Unknown command {:?}", s.trim())),
+        }
+    }
+}
+
+/// What the search reports after every depth it completes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Info {
+    pub depth: u32,
+    /// From the point of view of the side to move, see `MoveAnalysis::score`.
+    pub score: i32,
+    pub label: MoveLabel,
+    /// The nodes visited by every depth so far.
+    pub nodes: u64,
+    pub time: Duration,
+    pub principal_variation: Vec<FullCoord>,
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "info depth {} score ", self.depth)?;
+        match self.label {
+            MoveLabel::Winning => write!(f, "win {}", WIN_SCORE - self.score)?,
+            MoveLabel::Losing => write!(f, "loss {}", WIN_SCORE + self.score)?,
+            MoveLabel::Drawing | MoveLabel::Unknown => write!(f, "cp {}", self.score)?,
+        }
+        write!(f, " nodes {} time {} pv", self.nodes, self.time.as_millis())?;
+        for full_coord in &self.principal_variation {
+            write!(f, " {}", full_coord)?;
+        }
+        Ok(())
+    }
+}
+
+enum Message {
+    Line(String),
+    EndOfInput,
+    Info(Info),
+    BestMove(Option<Action>),
+}
+
+// Deepens the search one ply at a time until `max_depth` or until `stop` is
+// set, and picks the best action of the deepest search that finished.
+fn search(game: &Game, limits: SearchLimits, max_depth: u32, stop: &AtomicBool, sender: &Sender<Message>)
+    -> Option<Action>
+{
+    let start = Instant::now();
+    let mut nodes = 0;
+    let mut best = None;
+
+    for depth in 1..=max_depth {
+        let analyses = match analysis::analyze_until_stopped(game, &SearchLimits { depth, ..limits }, stop) {
+            Some(analyses) => analyses,
+            None => break,
+        };
+        nodes += analyses.iter().map(|analysis| analysis.nodes).sum::<u64>();
+        let analysis = match analyses.into_iter().max_by_key(|analysis| analysis.score) {
+            Some(analysis) => analysis,
+            None => break,
+        };
+
+        let _ = sender.send(Message::Info(Info {
+            depth,
+            score: analysis.score,
+            label: analysis.label,
+            nodes,
+            time: start.elapsed(),
+            principal_variation: analysis.principal_variation.clone(),
+        }));
+        let won = analysis.label == MoveLabel::Winning;
+        best = Some(analysis);
+        if won {
+            break;
+        }
+    }
+
+    match best {
+        // Swapping is worth as much as the best move is to the opponent, see `analysis::analyze_swap`
+        Some(ref best) if game.can_swap() && -best.score > best.score => Some(Action::Swap),
+        Some(best) => Some(Action::Move(best.full_coord)),
+        // Stopped before the first depth finished
+        None => game.moves().next().map(Action::Move),
+    }
+}
+
+/// The engine's side of the protocol, see the module documentation.
+pub struct Engine {
+    rules: RuleSet,
+    limits: SearchLimits,
+    game: Game,
+    sender: Sender<Message>,
+    // The flag to stop the running search, and when to set it
+    search: Option<(Arc<AtomicBool>, Option<Instant>)>,
+}
+
+impl Engine {
+    fn new(sender: Sender<Message>) -> Self {
+        Self {
+            rules: RuleSet::default(),
+            limits: SearchLimits::default(),
+            game: Game::new(),
+            sender,
+            search: None,
+        }
+    }
+
+    fn set_position(&mut self, setup: Option<&str>, actions: &[Action]) -> Result<(), String> {
+        let mut game = match setup {
+            Some(position) => Game::from_position_with_rules(self.rules, position)?,
+            None => Game::new_with_rules(self.rules),
+        };
+        for action in actions {
+            game.play_action(action)?;
+        }
+        self.game = game;
+        Ok(())
+    }
+
+    // Rules take effect from the next `newgame` or `position`
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match &name.to_lowercase()[..] {
+            "depth" => self.limits.depth = value.parse().map_err(|_| format!("Expected a depth for {}", name))?,
+            "nodes" => self.limits.nodes = value.parse().map_err(|_| format!("Expected a number of nodes for {}", name))?,
+            "pierule" => self.rules.pie_rule = match value {
+                "true" => PieRule::SwapAfterFirstMove,
+                "false" => PieRule::Off,
+                _ => return Err("Expected true or false for PieRule".into()),
+            },
+            _ => return Err(format!("Unknown option {:?}", name)),
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, limits: GoLimits) -> Result<(), String> {
+        if self.search.is_some() {
+            return Err("Already searching".into());
+        }
+
+        let max_depth = match limits.depth {
+            Some(depth) => depth,
+            None if limits.infinite || limits.movetime.is_some() =>
+                self.game.board.iter().filter(|&&square| square == Square::Empty).count() as u32,
+            None => self.limits.depth,
+        };
+        let search_limits = SearchLimits { nodes: limits.nodes.unwrap_or(self.limits.nodes), ..self.limits };
+        let stop = Arc::new(AtomicBool::new(false));
+        let deadline = limits.movetime.map(|movetime| Instant::now() + movetime);
+
+        let (game, flag, sender) = (self.game.clone(), stop.clone(), self.sender.clone());
+        thread::spawn(move || {
+            let best = search(&game, search_limits, max_depth, &flag, &sender);
+            let _ = sender.send(Message::BestMove(best));
+        });
+        self.search = Some((stop, deadline));
+        Ok(())
+    }
+
+    fn stop(&self) {
+        if let Some((ref stop, _)) = self.search {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.search.as_ref().and_then(|&(_, deadline)| deadline)
+    }
+
+    // Returns whether to keep going
+    fn handle<W: Write>(&mut self, command: Command, output: &mut W) -> io::Result<bool> {
+        let result = match command {
+            Command::NewGame => {
+                self.game = Game::new_with_rules(self.rules);
+                Ok(())
+            },
+            Command::Position { setup, actions } => self.set_position(setup.as_ref().map(|s| &s[..]), &actions),
+            Command::Go(limits) => self.go(limits),
+            Command::Stop => {
+                self.stop();
+                Ok(())
+            },
+            Command::SetOption { name, value } => self.set_option(&name, &value),
+            Command::IsReady => {
+                writeln!(output, "readyok")?;
+                Ok(())
+            },
+            Command::Quit => {
+                self.stop();
+                return Ok(false);
+            },
+        };
+
+        if let Err(err) = result {
+            writeln!(output, "info string {}", err)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Plays the protocol until `quit` or the end of `input`, which waits for
+/// the running search to finish first.
+pub fn run<R, W>(input: R, mut output: W) -> io::Result<()>
+    where R: BufRead + Send + 'static, W: Write
+{
+    let (sender, messages) = mpsc::channel();
+    let lines = sender.clone();
+    thread::spawn(move || {
+        for line in input.lines() {
+            match line {
+                Ok(line) => if lines.send(Message::Line(line)).is_err() {
+                    return;
+                },
+                Err(_) => break,
+            }
+        }
+        let _ = lines.send(Message::EndOfInput);
+    });
+
+    let mut engine = Engine::new(sender);
+    let mut input_ended = false;
+    loop {
+        let message = match engine.deadline() {
+            Some(deadline) => match messages.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(message) => message,
+                // The search answers with its best move once it sees the flag
+                Err(RecvTimeoutError::Timeout) => {
+                    engine.stop();
+                    continue;
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match messages.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            },
+        };
+
+        match message {
+            Message::Line(ref line) if line.trim().is_empty() => (),
+            Message::Line(line) => {
+                let keep_going = match line.parse() {
+                    Ok(command) => engine.handle(command, &mut output)?,
+                    Err(err) => {
+                        writeln!(output, "info string {}", err)?;
+                        true
+                    },
+                };
+                if !keep_going {
+                    break;
+                }
+            },
+            Message::EndOfInput => input_ended = true,
+            Message::Info(info) => writeln!(output, "{}", info)?,
+            Message::BestMove(best) => {
+                engine.search = None;
+                match best {
+                    Some(action) => writeln!(output, "bestmove {}", action)?,
+                    None => writeln!(output, "bestmove none")?,
+                }
+            },
+        }
+        output.flush()?;
+
+        if input_ended && engine.search.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
+        FullCoord::try_new(outer, inner).unwrap()
+    }
+
+    fn run_lines(input: &str) -> Vec<String> {
+        let mut output = Vec::new();
+        run(io::Cursor::new(input.to_string()), &mut output).unwrap();
+        String::from_utf8(output).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn commands_should_be_parsed() {
+        assert_eq!("position startpos moves 1,1:1,1 swap".parse(), Ok(Command::Position {
+            setup: None,
+            actions: vec![Action::Move(fc((1, 1), (1, 1))), Action::Swap],
+        }));
+        assert_eq!("go depth 3 movetime 100".parse(), Ok(Command::Go(GoLimits {
+            depth: Some(3),
+            movetime: Some(Duration::from_millis(100)),
+            ..GoLimits::default()
+        })));
+        assert_eq!("setoption name Search Depth value 3".parse(), Ok(Command::SetOption {
+            name: "Search Depth".into(),
+            value: "3".into(),
+        }));
+        assert!("go depth".parse::<Command>().is_err());
+        assert!("position".parse::<Command>().is_err());
+        assert!("stop now".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn a_position_should_be_set_up_from_a_position_string() {
+        let mut game = Game::new();
+        game.play_move(&fc((1, 1), (0, 2))).unwrap();
+        let command = format!("position setup {} moves 0,2:1,1", game.position_string());
+        game.play_move(&fc((0, 2), (1, 1))).unwrap();
+
+        let (sender, _) = mpsc::channel();
+        let mut engine = Engine::new(sender);
+        engine.handle(command.parse().unwrap(), &mut io::sink()).unwrap();
+
+        assert_eq!(engine.game.position_string(), game.position_string());
+    }
+
+    #[test]
+    fn a_search_should_report_every_depth_and_a_best_move() {
+        let output = run_lines("position startpos moves 1,1:1,1\ngo depth 2\n");
+
+        assert_eq!(output.len(), 3);
+        assert!(output[0].starts_with("info depth 1 score cp "));
+        assert!(output[1].starts_with("info depth 2 score cp "));
+        assert!(output[1].contains(" pv 1,1:"));
+        assert!(output[2].starts_with("bestmove 1,1:"));
+    }
+
+    #[test]
+    fn a_finished_game_should_have_no_best_move() {
+        // X completes the middle column of the outer board with the last move
+        let moves = "1,1:1,0 1,0:1,1 1,1:1,2 1,2:1,1 1,1:1,1 1,0:0,1 0,1:1,1 0,0:0,1 0,1:0,1 0,1:2,0 2,0:1,1 \
+            2,0:0,1 0,1:2,1 2,1:0,1 2,1:2,1 2,1:0,2 0,2:0,1 1,2:2,1 2,1:2,2 2,2:2,1 2,1:2,0";
+        let output = run_lines(&format!("position startpos moves {}\ngo depth 1\n", moves));
+
+        assert_eq!(output, vec!["bestmove none"]);
+    }
+
+    #[test]
+    fn errors_should_be_reported_and_ignored() {
+        let output = run_lines("hello\nposition startpos moves 0,0:0,0 0,0:0,0\nsetoption name Colour value red\n\
+            setoption name Depth value 5000000000\nisready\n");
+
+        assert_eq!(output.len(), 5);
+        assert!(output[..4].iter().all(|line| line.starts_with("info string ")));
+        assert_eq!(output[4], "readyok");
+    }
+
+    #[test]
+    fn the_pie_rule_option_should_allow_swaps() {
+        let output = run_lines("setoption name PieRule value true\nposition startpos moves 1,1:1,1 swap\nisready\n");
+        assert_eq!(output, vec!["readyok"]);
+
+        let output = run_lines("position startpos moves 1,1:1,1 swap\n");
+        assert_eq!(output.len(), 1);
+        assert!(output[0].starts_with("info string "));
+    }
+}
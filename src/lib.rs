@@ -1,3 +1,20 @@
+pub mod analysis;
+pub mod annotate;
+pub mod codingame;
+pub mod engine;
+pub mod nested;
+pub mod openings;
+pub mod player;
+pub mod puzzle;
+
+mod rng;
+
+use std::cmp;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+/// Size of the inner and outer boards in the standard game.
 pub const SIZE: usize = 3;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -8,16 +25,25 @@ pub struct Coord {
 
 impl Coord {
     fn new(x: usize, y: usize) -> Self {
-        Self { x: x, y: y }
+        Self { x, y }
     }
 
     pub fn try_new(x: usize, y:usize) -> Result<Self, String> {
+        Self::try_new_sized(x, y, SIZE)
+    }
+
+    /// Like `try_new`, but for boards of the given size.
+    pub fn try_new_sized(x: usize, y: usize, size: usize) -> Result<Self, String> {
         let c = Self::new(x, y);
-        if c.is_valid() { Ok(c) } else { Err("Out of bounds".into()) }
+        if c.is_valid_for(size) { Ok(c) } else { Err("Out of bounds".into()) }
     }
 
     pub fn is_valid(&self) -> bool {
-        self.x < SIZE && self.y < SIZE
+        self.is_valid_for(SIZE)
+    }
+
+    pub fn is_valid_for(&self, size: usize) -> bool {
+        self.x < size && self.y < size
     }
 
     pub fn x(&self) -> usize { self.x }
@@ -33,18 +59,46 @@ pub struct FullCoord {
 impl FullCoord {
     fn new(outer: Coord, inner: Coord) -> Self {
         Self {
-            outer: outer,
-            inner: inner,
+            outer,
+            inner,
         }
     }
 
-    pub fn try_new((ox, oy): (usize, usize), (ix, iy): (usize, usize)) -> Result<Self, String> {
+    pub fn try_new(outer: (usize, usize), inner: (usize, usize)) -> Result<Self, String> {
+        Self::try_new_sized(outer, inner, SIZE)
+    }
+
+    /// Like `try_new`, but for boards of the given size.
+    pub fn try_new_sized((ox, oy): (usize, usize), (ix, iy): (usize, usize), size: usize) -> Result<Self, String> {
         let c = Self::new(Coord::new(ox, oy), Coord::new(ix, iy));
-        if c.is_valid() { Ok(c) } else { Err("Out of bounds".into()) }
+        if c.is_valid_for(size) { Ok(c) } else { Err("Out of bounds".into()) }
+    }
+
+    /// Like `FromStr`, but for boards of the given size.
+    pub fn parse_sized(s: &str, size: usize) -> Result<Self, String> {
+        let parse_coord = |coord: &str| -> Result<(usize, usize), String> {
+            let mut parts = coord.split(',').map(|part| part.trim().parse::<usize>());
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok((x, y)),
+                _ => Err(format!("Malformed coordinate: {:?}", s)),
+            }
+        };
+
+        let mut parts = s.split(':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(outer), Some(inner), None) => {
+                Self::try_new_sized(parse_coord(outer)?, parse_coord(inner)?, size)
+            },
+            _ => Err(format!("Malformed coordinate: {:?}", s)),
+        }
     }
 
     pub fn is_valid(&self) -> bool {
-        self.outer.is_valid() && self.inner.is_valid()
+        self.is_valid_for(SIZE)
+    }
+
+    pub fn is_valid_for(&self, size: usize) -> bool {
+        self.outer.is_valid_for(size) && self.inner.is_valid_for(size)
     }
 
     pub fn inner(&self) -> Coord { self.inner }
@@ -54,26 +108,172 @@ impl FullCoord {
     pub fn inner_y(&self) -> usize { self.inner.y() }
     pub fn outer_x(&self) -> usize { self.outer.x() }
     pub fn outer_y(&self) -> usize { self.outer.y() }
+
+    /// The `(col, row)` of the square on the grid of all `size * size` by
+    /// `size * size` squares, counted from the top left like `x` and `y`.
+    pub fn to_global(&self, size: usize) -> (usize, usize) {
+        (self.outer.x * size + self.inner.x, self.outer.y * size + self.inner.y)
+    }
+
+    /// The square at the given `(col, row)`, see `to_global`.
+    pub fn try_from_global((col, row): (usize, usize), size: usize) -> Result<Self, String> {
+        Self::try_new_sized((col / size, row / size), (col % size, row % size), size)
+    }
+
+    /// The number of the square when reading the grid of `to_global` row by
+    /// row, as `Game::position_string` does. It's below `size.pow(4)`.
+    pub fn to_index(&self, size: usize) -> usize {
+        let (col, row) = self.to_global(size);
+        row * size * size + col
+    }
+
+    /// The square with the given number, see `to_index`.
+    pub fn try_from_index(index: usize, size: usize) -> Result<Self, String> {
+        let width = size * size;
+        Self::try_from_global((index % width, index / width), size)
+    }
+
+    /// The next square in `direction` on the grid of `to_global`, wrapping
+    /// around to the other side at the edges.
+    pub fn neighbour(&self, direction: Direction, size: usize) -> Self {
+        let width = size * size;
+        let (col, row) = self.to_global(size);
+        let global = match direction {
+            Direction::Up => (col, (row + width - 1) % width),
+            Direction::Down => (col, (row + 1) % width),
+            Direction::Left => ((col + width - 1) % width, row),
+            Direction::Right => ((col + 1) % width, row),
+        };
+        Self::try_from_global(global, size).unwrap()
+    }
+}
+
+/// A direction on the board, with up towards the first row.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl fmt::Display for FullCoord {
+    /// Formats as `outer_x,outer_y:inner_x,inner_y`, for example `1,1:0,2`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}:{},{}", self.outer.x, self.outer.y, self.inner.x, self.inner.y)
+    }
+}
+
+impl FromStr for FullCoord {
+    type Err = String;
+
+    /// Parses the format written by `Display`, for the standard board size.
+    /// Use `FullCoord::parse_sized` for other sizes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_sized(s, SIZE)
+    }
+}
+
+/// Anything a player can do on their turn.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Move(FullCoord),
+    /// Take over the opponent's side, see `PieRule`.
+    Swap,
+}
+
+impl fmt::Display for Action {
+    /// Formats moves like `FullCoord`, and a swap as `swap`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Move(full_coord) => write!(f, "{}", full_coord),
+            Action::Swap => write!(f, "swap"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "swap" => Ok(Action::Swap),
+            s => s.parse().map(Action::Move),
+        }
+    }
 }
 
+/// The most players a game can have, see `RuleSet::players`.
+pub const MAX_PLAYERS: usize = 4;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Piece {
     X,
     O,
+    /// Δ, only used with three or more players.
+    Triangle,
+    /// □, only used with four players.
+    Box,
 }
 
 impl Piece {
-    pub fn opposite(&self) -> Self {
+    /// Every piece, in turn order.
+    pub const ALL: [Piece; MAX_PLAYERS] = [Piece::X, Piece::O, Piece::Triangle, Piece::Box];
+
+    /// The pieces of a game with the given number of players, in turn order.
+    pub fn all(players: usize) -> &'static [Piece] {
+        &Self::ALL[..players]
+    }
+
+    /// The piece that moves after this one, in a game with the given number of players.
+    pub fn next(&self, players: usize) -> Self {
+        let index = Self::ALL.iter().position(|piece| piece == self).unwrap();
+        Self::ALL[(index + 1) % players]
+    }
+
+    /// The other piece of a two-player game, or `None` for the pieces
+    /// that only exist with more players.
+    pub fn opposite(&self) -> Option<Self> {
+        match self {
+            Piece::X => Some(Piece::O),
+            Piece::O => Some(Piece::X),
+            Piece::Triangle | Piece::Box => None,
+        }
+    }
+
+    // How the piece is written in `Game::position_string`
+    fn letter(&self) -> char {
+        match self {
+            Piece::X => 'x',
+            Piece::O => 'o',
+            Piece::Triangle => 't',
+            Piece::Box => 'b',
+        }
+    }
+
+    /// The symbol drawn for this piece.
+    pub fn symbol(&self) -> char {
         match self {
-            Piece::X => Piece::O,
-            Piece::O => Piece::X,
+            Piece::X => 'X',
+            Piece::O => 'O',
+            Piece::Triangle => 'Δ',
+            Piece::Box => '□',
         }
     }
 }
 
+/// A square that can be part of a line, either on an inner board or on the outer board.
+///
+/// `filling_piece` is `None` for drawn inner boards, so by default they block
+/// every line. `counts_for` lets `RuleSet::drawn_boards` treat them as wild.
 trait FilledSquare {
     fn is_filled(&self) -> bool;
     fn filling_piece(&self) -> Option<Piece>;
+
+    /// Whether this square counts towards a line of `piece`.
+    fn counts_for(&self, piece: Piece, _rules: &RuleSet) -> bool {
+        self.filling_piece() == Some(piece)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -84,14 +284,14 @@ pub enum Square {
 
 impl FilledSquare for Square {
     fn is_filled(&self) -> bool {
-        if let Square::Filled(_) = self { true } else { false }
+        matches!(self, Square::Filled(_))
     }
     fn filling_piece(&self) -> Option<Piece> {
         if let Square::Filled(piece) = self { Some(*piece) } else { None }
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum OuterSquare {
     Incomplete,
     Complete(GameOverResult),
@@ -99,7 +299,7 @@ pub enum OuterSquare {
 
 impl FilledSquare for OuterSquare {
     fn is_filled(&self) -> bool {
-        if let OuterSquare::Complete(_) = self { true } else { false }
+        matches!(self, OuterSquare::Complete(_))
     }
     fn filling_piece(&self) -> Option<Piece> {
         match self {
@@ -107,45 +307,362 @@ impl FilledSquare for OuterSquare {
             _ => None,
         }
     }
+    fn counts_for(&self, piece: Piece, rules: &RuleSet) -> bool {
+        match self {
+            OuterSquare::Complete(GameOverResult::Draw) =>
+                rules.drawn_boards == DrawnBoardRule::CountsForBoth,
+            _ => self.filling_piece() == Some(piece),
+        }
+    }
 }
 
+#[derive(Clone, PartialEq, Debug)]
+pub enum WinReason {
+    /// Completed the given lines.
+    Line(Lines),
+    /// The opponent completed the given lines, which loses under `Misere` rules.
+    MisereLine(Lines),
+    /// Completed more lines on the outer board, see `OuterLineRule::MostLinesWin`.
+    /// Holds the number of lines of the winner and of the opponent.
+    MostLines(usize, usize),
+    /// Won more inner boards once the outer board was full, see `Tiebreak::MostBoards`.
+    /// Holds the number of boards of the winner and of the opponent.
+    MostBoards(usize, usize),
+    /// The opponent resigned.
+    Resignation,
+    /// The opponent forfeited the game.
+    Forfeit(ForfeitReason),
+}
+
+/// Why a player forfeited, see `Game::forfeit`.
 #[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ForfeitReason {
+    Timeout,
+    IllegalMove,
+    Disconnection,
+}
+
+/// Why a move can't be played, see `Game::move_legality`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum IllegalMove {
+    GameOver(GameOverResult),
+    OutOfBounds,
+    /// The square already holds the given piece.
+    Occupied(Piece),
+    /// Moves have to be played in the given active outer square.
+    OutsideActiveSquare(Coord),
+    /// The outer square is complete, with the given result.
+    CompletedOuterSquare(GameOverResult),
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IllegalMove::GameOver(_) => write!(f, "Attempt to play on a finished game"),
+            IllegalMove::OutOfBounds => write!(f, "Out of bounds"),
+            IllegalMove::Occupied(piece) => write!(f, "Attempt to play on a square taken by {}", piece.symbol()),
+            IllegalMove::OutsideActiveSquare(active) =>
+                write!(f, "Attempt to play outside active outer square {},{}", active.x, active.y),
+            IllegalMove::CompletedOuterSquare(_) => write!(f, "Attempt to play in a completed outer square"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum GameOverResult {
     Draw,
-    Winner(
-        Piece,
-        ((usize, usize), (usize, usize), (usize, usize)),
-    ),
+    Winner(Piece, WinReason),
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum GameState {
     InProgress,
     GameOver(GameOverResult),
+    /// Both players agreed to a draw, see `Game::offer_draw`. It ends the
+    /// game without a result on the board, so boards never end this way.
+    AgreedDraw,
+}
+
+impl GameState {
+    /// The result of a finished game, with an agreed draw counting as a draw.
+    pub fn result(&self) -> Option<GameOverResult> {
+        match *self {
+            GameState::InProgress => None,
+            GameState::GameOver(ref result) => Some(result.clone()),
+            GameState::AgreedDraw => Some(GameOverResult::Draw),
+        }
+    }
+}
+
+/// What happens when a move sends the opponent to a completed inner board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CompletedBoardRule {
+    /// The opponent may play in any incomplete inner board.
+    FreeMove,
+    /// The opponent still has to play in that board, as long as it has
+    /// empty squares. Only a full board gives a free move.
+    PlayInside,
+}
+
+/// Whether drawn inner boards help to complete lines on the outer board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DrawnBoardRule {
+    CountsForNobody,
+    CountsForBoth,
+}
+
+/// When the game is decided by lines on the outer board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OuterLineRule {
+    /// The first completed line wins the game.
+    FirstLineWins,
+    /// Play continues until every inner board is complete, and the player
+    /// with the most completed lines wins.
+    MostLinesWin,
+}
+
+/// How a game that would otherwise end in a draw is decided.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tiebreak {
+    None,
+    /// The player who won more inner boards wins.
+    MostBoards,
+}
+
+/// Whether completing a line loses instead of wins.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Misere {
+    Off,
+    /// Only lines on the outer board lose.
+    OuterBoard,
+    /// Lines on inner boards lose as well, giving the board to the opponent.
+    AllBoards,
+}
+
+/// Whether the second player may swap sides after the first move.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PieRule {
+    Off,
+    /// Instead of replying to X's first move, O may take it over. The
+    /// players swap sides, and the first player goes on to move as O.
+    SwapAfterFirstMove,
+}
+
+/// Whether boards that can no longer be won are ended early.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DeadDrawRule {
+    /// Boards are only drawn once they are full.
+    PlayOut,
+    /// An inner board is drawn as soon as no line on it can be completed,
+    /// and the game as soon as `Game::is_dead_draw` says so.
+    EndEarly,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RuleSet {
+    /// Number of rows and columns, of the outer board and of every inner board.
+    pub size: usize,
+    /// How many squares in a row complete a line.
+    pub win_length: usize,
+    /// Number of players, from 2 to `MAX_PLAYERS`. Turns go through `Piece::ALL` in order.
+    pub players: usize,
+    pub completed_boards: CompletedBoardRule,
+    pub drawn_boards: DrawnBoardRule,
+    pub outer_lines: OuterLineRule,
+    pub tiebreak: Tiebreak,
+    pub misere: Misere,
+    pub pie_rule: PieRule,
+    pub dead_draws: DeadDrawRule,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            size: SIZE,
+            win_length: SIZE,
+            players: 2,
+            completed_boards: CompletedBoardRule::FreeMove,
+            drawn_boards: DrawnBoardRule::CountsForNobody,
+            outer_lines: OuterLineRule::FirstLineWins,
+            tiebreak: Tiebreak::None,
+            misere: Misere::Off,
+            pie_rule: PieRule::Off,
+            dead_draws: DeadDrawRule::PlayOut,
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn validate(&self) -> Result<(), String> {
+        // Lines store their squares in bytes, and a single square would be a
+        // line in every orientation at once
+        if self.size < 2 || self.size > u8::MAX as usize {
+            Err(format!("Board size must be between 2 and {}", u8::MAX))
+        } else if self.win_length < 2 || self.win_length > self.size {
+            Err(format!("Win length must be between 2 and the board size {}", self.size))
+        } else if self.players < 2 || self.players > MAX_PLAYERS {
+            Err(format!("The number of players must be between 2 and {}", MAX_PLAYERS))
+        } else if self.players > 2 && self.misere != Misere::Off {
+            Err("Misère rules need exactly two players".into())
+        } else if self.players > 2 && self.pie_rule != PieRule::Off {
+            Err("The pie rule needs exactly two players".into())
+        } else {
+            Ok(())
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Game {
+    pub rules: RuleSet,
     pub state: GameState,
-    pub board: [[[[Square; SIZE]; SIZE]; SIZE]; SIZE],
-    pub outer_board: [[OuterSquare; SIZE]; SIZE],
+    /// Every square, indexed by outer x, outer y, inner x and inner y,
+    /// from the most to the least significant.
+    pub board: Vec<Square>,
+    /// Indexed by x and y, from the most to the least significant.
+    pub outer_board: Vec<OuterSquare>,
     pub turn: Piece,
     pub active_outer_square: Option<Coord>,
+    /// The number of moves played, which decides when the players may swap
+    /// sides, see `PieRule`. Swapping isn't a move.
+    pub moves_played: usize,
+    /// Whether the players swapped sides, see `PieRule`.
+    pub swapped: bool,
+    /// The player whose draw offer is waiting for an answer.
+    pub draw_offer: Option<Piece>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Self {
+        Self::new_with_rules(RuleSet::default())
+    }
+
+    /// Panics if the rules don't pass `RuleSet::validate`.
+    pub fn new_with_rules(rules: RuleSet) -> Self {
+        if let Err(err) = rules.validate() {
+            panic!("Invalid rules: {}", err);
+        }
+
+        let outer_count = rules.size * rules.size;
         Self {
+            rules,
             state: GameState::InProgress,
-            board: [[[[Square::Empty; SIZE]; SIZE]; SIZE]; SIZE],
-            outer_board: [[OuterSquare::Incomplete; SIZE]; SIZE],
+            board: vec![Square::Empty; outer_count * outer_count],
+            outer_board: vec![OuterSquare::Incomplete; outer_count],
             turn: Piece::X,
             active_outer_square: None,
+            moves_played: 0,
+            swapped: false,
+            draw_offer: None,
+        }
+    }
+
+    /// Sets up a game from a position in the notation of `position_string`,
+    /// to continue a saved game, set up a teaching scenario or give a player
+    /// a head start, such as an inner board won in advance.
+    pub fn from_position(position: &str) -> Result<Self, String> {
+        Self::from_position_with_rules(RuleSet::default(), position)
+    }
+
+    /// Completed inner boards and the state of the game follow from the
+    /// squares. Only the side to move and the active outer square have to
+    /// agree with the rules; the number of pieces of every player doesn't,
+    /// so that handicaps can be given.
+    pub fn from_position_with_rules(rules: RuleSet, position: &str) -> Result<Self, String> {
+        rules.validate()?;
+        let mut game = Self::new_with_rules(rules);
+        let size = game.size();
+        let malformed = || format!("Malformed position: {:?}", position);
+
+        let mut parts = position.split_whitespace();
+        let (board, turn, active) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(board), Some(turn), Some(active)) => (board, turn, active),
+            _ => return Err(malformed()),
+        };
+        let moves_played = match parts.next() {
+            Some(moves_played) => Some(moves_played.parse::<usize>().map_err(|_| malformed())?),
+            None => None,
+        };
+        let piece = |letter| Piece::all(rules.players).iter().cloned()
+            .find(|piece| piece.letter() == letter)
+            .ok_or_else(|| format!("No player plays {:?} in a game of {}", letter, rules.players));
+
+        let rows: Vec<_> = board.split('/').collect();
+        if rows.len() != size * size || rows.iter().any(|row| row.chars().count() != size * size) {
+            return Err(format!("The board needs {} rows of {} squares", size * size, size * size));
+        }
+        for (row, squares) in rows.iter().enumerate() {
+            for (col, letter) in squares.chars().enumerate() {
+                let full_coord = FullCoord::try_from_global((col, row), size)?;
+                let square = if letter == '.' { Square::Empty } else { Square::Filled(piece(letter)?) };
+                game.set_square(&full_coord, square);
+            }
+        }
+
+        let mut turn = turn.chars();
+        game.turn = match (turn.next(), turn.next()) {
+            (Some(letter), None) => piece(letter)?,
+            _ => return Err(malformed()),
+        };
+
+        for x in 0..size {
+            for y in 0..size {
+                game.update_outer_square(&Coord::new(x, y));
+            }
+        }
+
+        if active != "-" {
+            let mut coords = active.split(',').map(str::parse);
+            let outer = match (coords.next(), coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Coord::try_new_sized(x, y, size)?,
+                _ => return Err(malformed()),
+            };
+            if !game.is_playable(&outer) {
+                return Err(format!("The active outer square {},{} can't be played in", outer.x, outer.y));
+            }
+            game.active_outer_square = Some(outer);
         }
+
+        // Positions written without the number of moves count every piece as a move
+        game.moves_played = moves_played
+            .unwrap_or_else(|| game.board.iter().filter(|square| square.is_filled()).count());
+        for flag in parts {
+            if flag == "swapped" && rules.pie_rule != PieRule::Off {
+                game.swapped = true;
+            } else if let Some(letter) = flag.strip_prefix("draw-") {
+                let mut letters = letter.chars();
+                let piece = match (letters.next(), letters.next()) {
+                    (Some(letter), None) => piece(letter)?,
+                    _ => return Err(malformed()),
+                };
+                game.opponent(piece)?;
+                game.draw_offer = Some(piece);
+            } else {
+                return Err(malformed());
+            }
+        }
+
+        game.update_state();
+        Ok(game)
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    /// Number of rows and columns of the outer board and of every inner board.
+    pub fn size(&self) -> usize {
+        self.rules.size
     }
 
     pub fn state(&self) -> GameState {
-        self.state
+        self.state.clone()
     }
 
     pub fn turn(&self) -> Piece {
@@ -156,209 +673,879 @@ impl Game {
         self.active_outer_square
     }
 
-    pub fn get_outer_square(&self, coord: &Coord) -> OuterSquare {
-        self.outer_board[coord.x()][coord.y()]
+    pub fn moves_played(&self) -> usize {
+        self.moves_played
     }
 
-    pub fn get_square(&self, full_coord: &FullCoord) -> Square {
-        let Coord { x: ox, y: oy } = full_coord.outer;
-        let Coord { x: ix, y: iy } = full_coord.inner;
+    pub fn swapped(&self) -> bool {
+        self.swapped
+    }
 
-        self.board[ox][oy][ix][iy]
+    pub fn draw_offer(&self) -> Option<Piece> {
+        self.draw_offer
     }
 
-    fn set_square(&mut self, full_coord: &FullCoord, piece: Square) {
-        let Coord { x: ox, y: oy } = full_coord.outer;
-        let Coord { x: ix, y: iy } = full_coord.inner;
+    // Panics for coordinates off the board, which would otherwise index another square
+    fn outer_index(&self, coord: &Coord) -> usize {
+        assert!(coord.is_valid_for(self.size()), "{},{} is off a board of size {}", coord.x, coord.y, self.size());
+        coord.x * self.size() + coord.y
+    }
 
-        self.board[ox][oy][ix][iy] = piece;
+    fn square_index(&self, full_coord: &FullCoord) -> usize {
+        let inner_count = self.size() * self.size();
+        self.outer_index(&full_coord.outer) * inner_count + self.outer_index(&full_coord.inner)
     }
 
-    fn get_moves_inner_board(&self, outer: &Coord, moves: &mut Vec<FullCoord>) {
-        let mut full_coord = FullCoord::new(
-            *outer,
-            Coord::new(0, 0),
-        );
-        for ix in 0..SIZE {
-            full_coord.inner.x = ix;
-            for iy in 0..SIZE {
-                full_coord.inner.y = iy;
-
-                match self.get_square(&full_coord) {
-                    Square::Empty => {
-                        moves.push(full_coord)
-                    },
-                    _ => (),
-                }
-            }
-        }
+    // The squares of one inner board, indexed like the outer board
+    fn inner_board(&self, outer: &Coord) -> &[Square] {
+        let inner_count = self.size() * self.size();
+        let start = self.outer_index(outer) * inner_count;
+        &self.board[start..start + inner_count]
     }
 
-    pub fn get_moves(&self) -> Vec<FullCoord> {
-        if let GameState::GameOver(_) = self.state {
-            vec![]
-        } else if let Some(active) = self.active_outer_square {
-            let mut moves = Vec::with_capacity(SIZE * SIZE);
-            self.get_moves_inner_board(&active, &mut moves);
-            moves
+    pub fn get_outer_square(&self, coord: &Coord) -> OuterSquare {
+        self.outer_board[self.outer_index(coord)].clone()
+    }
 
-        } else {
-            let mut moves = Vec::with_capacity(SIZE * SIZE * SIZE * SIZE);
-            for x in 0..SIZE {
-                for y in 0..SIZE {
-                    if let OuterSquare::Incomplete = self.outer_board[x][y] {
-                        self.get_moves_inner_board(&Coord::new(x, y), &mut moves);
-                    }
-                }
-            }
-            moves
-        }
+    pub fn get_square(&self, full_coord: &FullCoord) -> Square {
+        self.board[self.square_index(full_coord)]
+    }
+
+    // Whether `piece` could still end up owning the incomplete inner board at `outer`
+    fn can_take_board(&self, outer: &Coord, piece: Piece) -> bool {
+        // Under misère rules the board goes to the opponent of whoever completes a line
+        let line_piece = match piece.opposite() {
+            Some(opposite) if self.rules.misere == Misere::AllBoards => opposite,
+            _ => piece,
+        };
+        let board = self.inner_board(outer);
+        let square = |coord: Coord| board[coord.x * self.size() + coord.y];
+        has_open_line(
+            &self.rules,
+            |coord| square(coord) == Square::Empty || square(coord) == Square::Filled(line_piece),
+            |coord| square(coord) == Square::Empty,
+        )
     }
 
-    pub fn play_move(&mut self, full_coord: &FullCoord) -> Result<(), String> {
-        if let GameState::GameOver(_) = self.state {
-            return Err("Attempt to play on a finished game".into());
+    /// Whether the inner board at `outer` is drawn, or can only end in a draw
+    /// because neither player can complete a line on it anymore.
+    pub fn is_dead_board(&self, outer: &Coord) -> bool {
+        match self.get_outer_square(outer) {
+            OuterSquare::Complete(result) => result == GameOverResult::Draw,
+            OuterSquare::Incomplete =>
+                Piece::all(self.rules.players).iter().all(|&piece| !self.can_take_board(outer, piece)),
         }
+    }
 
-        if let Square::Filled(_) = self.get_square(full_coord) {
-            return Err("Attempt to play on a non-empty square".into());
+    /// Whether the game is drawn, or can only end in a draw however it's played on.
+    ///
+    /// This is the case once no line on the outer board can be completed
+    /// anymore, counting inner boards that can no longer be won as drawn.
+    /// With `OuterLineRule::MostLinesWin` the completed lines also have to be
+    /// tied. Games with a `Tiebreak` are never considered dead early.
+    pub fn is_dead_draw(&self) -> bool {
+        match self.state {
+            GameState::GameOver(GameOverResult::Draw) | GameState::AgreedDraw => return true,
+            GameState::GameOver(_) => return false,
+            GameState::InProgress => (),
         }
-        if let Some(active) = self.active_outer_square {
-            if active != full_coord.outer {
-                return Err("Attempt to play outside active outer square".into());
-            }
+        if self.rules.tiebreak != Tiebreak::None {
+            return false;
         }
 
-        // TODO: why doesn't this work if the variable is inlined?
-        let square = Square::Filled(self.turn);
-        self.set_square(full_coord, square);
+        let size = self.size();
+        let counts_for_both = self.rules.drawn_boards == DrawnBoardRule::CountsForBoth;
+        let open_for = |piece: Piece| has_open_line(
+            &self.rules,
+            |coord| match self.outer_board[coord.x * size + coord.y] {
+                ref square @ OuterSquare::Complete(_) => square.counts_for(piece, &self.rules),
+                // An incomplete board might still end in a draw that counts for both
+                OuterSquare::Incomplete => counts_for_both || self.can_take_board(&coord, piece),
+            },
+            |coord| self.outer_board[coord.x * size + coord.y] == OuterSquare::Incomplete,
+        );
+        if Piece::all(self.rules.players).iter().any(|&piece| open_for(piece)) {
+            return false;
+        }
 
-        self.turn = self.turn.opposite();
+        match self.rules.outer_lines {
+            OuterLineRule::FirstLineWins => true,
+            OuterLineRule::MostLinesWin => {
+                let lines = completed_lines(&self.outer_board, &self.rules);
+                let count = |piece| lines.iter().filter(|&&(winner, _)| winner == piece).count();
+                highest_count(&self.rules, count).is_none()
+            },
+        }
+    }
 
-        // check for game over conditions on the inner board
-        // and update the outer board if necessary
-        let Coord { x, y } = full_coord.outer();
-        if let Some(result) = check_result(&self.board[x][y]) {
-            self.outer_board[x][y] = OuterSquare::Complete(result);
+    /// Describes the position as text.
+    ///
+    /// The board is written row by row, with rows separated by `/` and a
+    /// letter per square: `x`, `o`, `t` (Δ), `b` (□) or `.` if it's empty.
+    /// That's followed by the side to move, the active outer square as
+    /// `x,y` (or `-` if any square may be played) and the number of moves
+    /// played. For example the game after X played in the centre is
+    /// `........./........./........./........./....x..../........./........./........./......... o 1,1 1`.
+    /// Last come `swapped` if the players swapped sides and `draw-x` (or
+    /// another piece's letter) while that player's draw offer stands.
+    ///
+    /// `from_position` sets up a game from it.
+    pub fn position_string(&self) -> String {
+        let size = self.size();
+        let mut rows = Vec::with_capacity(size * size);
+        for row in 0..size * size {
+            let mut squares = String::with_capacity(size * size);
+            for col in 0..size * size {
+                let full_coord = FullCoord::try_from_global((col, row), size).unwrap();
+                squares.push(match self.get_square(&full_coord) {
+                    Square::Empty => '.',
+                    Square::Filled(piece) => piece.letter(),
+                });
+            }
+            rows.push(squares);
         }
 
-        // update the active outer square
-        let Coord { x, y } = full_coord.inner;
-        self.active_outer_square = match self.outer_board[x][y] {
-            OuterSquare::Incomplete => Some(full_coord.inner),
-            OuterSquare::Complete(_) => None,
+        let turn = self.turn.letter();
+        let active = match self.active_outer_square {
+            Some(Coord { x, y }) => format!("{},{}", x, y),
+            None => "-".into(),
         };
 
-        // check for game over conditions of the full board
-        if let Some(result) = check_result(&self.outer_board) {
-            self.state = GameState::GameOver(result);
-            self.active_outer_square = None;
+        let mut position = format!("{} {} {} {}", rows.join("/"), turn, active, self.moves_played);
+        if self.swapped {
+            position.push_str(" swapped");
         }
+        if let Some(piece) = self.draw_offer {
+            position.push_str(&format!(" draw-{}", piece.letter()));
+        }
+        position
+    }
 
-        Ok(())
+    fn set_square(&mut self, full_coord: &FullCoord, piece: Square) {
+        let index = self.square_index(full_coord);
+        self.board[index] = piece;
     }
-}
 
-fn check_result<T: FilledSquare>(board: &[[T; SIZE]; SIZE]) -> Option<GameOverResult> {
-    const WINNING_TRIPLES: [(usize, usize, usize, usize, usize, usize); 8] = [
-        // Columns
-        (0, 0,  0, 1,  0, 2),
-        (1, 0,  1, 1,  1, 2),
-        (2, 0,  2, 1,  2, 2),
-        // Row
-        (0, 0,  1, 0,  2, 0),
-        (0, 1,  1, 1,  2, 1),
-        (0, 2,  1, 2,  2, 2),
-        // Diagonals
-        (0, 0,  1, 1,  2, 2),
-        (0, 2,  1, 1,  2, 0),
-    ];
-
-    for (x0, y0,  x1, y1,  x2, y2) in WINNING_TRIPLES.iter() {
-        let a = &board[*x0][*y0].filling_piece();
-        let b = &board[*x1][*y1].filling_piece();
-        let c = &board[*x2][*y2].filling_piece();
-
-        if a == b && b == c {
-            if let Some(winner) = a {
-                return Some(GameOverResult::Winner(
-                    *winner,
-                    ((*x0, *y0), (*x1, *y1), (*x2, *y2))
-                ));
-            }
+    // Whether moves may be played in the given inner board
+    fn is_playable(&self, outer: &Coord) -> bool {
+        match (self.get_outer_square(outer), self.rules.completed_boards) {
+            (OuterSquare::Incomplete, _) => true,
+            (OuterSquare::Complete(_), CompletedBoardRule::FreeMove) => false,
+            (OuterSquare::Complete(_), CompletedBoardRule::PlayInside) =>
+                self.inner_board(outer).contains(&Square::Empty),
         }
-    };
+    }
 
-    // Draw
-    for x in 0..SIZE {
-        for y in 0..SIZE {
-            if !&board[x][y].is_filled() {
-                return None;
-            }
-        }
+    /// Iterates over the legal moves without allocating, in the order of `get_moves`.
+    pub fn moves(&self) -> Moves<'_> {
+        let inner_count = self.size() * self.size();
+        let range = match (&self.state, self.active_outer_square) {
+            (GameState::GameOver(_), _) | (GameState::AgreedDraw, _) => 0..0,
+            (GameState::InProgress, Some(active)) => {
+                let start = self.outer_index(&active) * inner_count;
+                start..start + inner_count
+            },
+            (GameState::InProgress, None) => 0..self.board.len(),
+        };
+        Moves { game: self, range }
     }
 
-    return Some(GameOverResult::Draw);
-}
+    pub fn get_moves(&self) -> Vec<FullCoord> {
+        self.moves().collect()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Whether `full_coord` may be played next, the same as being one of `get_moves`.
+    pub fn is_legal(&self, full_coord: &FullCoord) -> bool {
+        self.move_legality(full_coord).is_ok()
+    }
 
-    fn c(x: usize, y: usize) -> Coord {
-        Coord::try_new(x, y).unwrap()
+    /// Checks whether `full_coord` may be played next, and if not, explains why.
+    pub fn move_legality(&self, full_coord: &FullCoord) -> Result<(), IllegalMove> {
+        if let Some(result) = self.state.result() {
+            return Err(IllegalMove::GameOver(result));
+        }
+
+        if !full_coord.is_valid_for(self.size()) {
+            return Err(IllegalMove::OutOfBounds);
+        }
+        if let Square::Filled(piece) = self.get_square(full_coord) {
+            return Err(IllegalMove::Occupied(piece));
+        }
+        match (self.active_outer_square, self.get_outer_square(&full_coord.outer)) {
+            (Some(active), _) if active != full_coord.outer => Err(IllegalMove::OutsideActiveSquare(active)),
+            (None, OuterSquare::Complete(result)) if !self.is_playable(&full_coord.outer) =>
+                Err(IllegalMove::CompletedOuterSquare(result)),
+            _ => Ok(()),
+        }
     }
 
-    fn ct((x, y): (usize, usize)) -> Coord {
-        Coord::try_new(x, y).unwrap()
+    /// The outer squares moves may be played in: the active one, or every
+    /// playable one if the move is free. Empty once the game is over.
+    pub fn playable_outer_squares(&self) -> Vec<Coord> {
+        match (&self.state, self.active_outer_square) {
+            (GameState::GameOver(_), _) | (GameState::AgreedDraw, _) => vec![],
+            (GameState::InProgress, Some(active)) => vec![active],
+            (GameState::InProgress, None) => (0..self.size())
+                .flat_map(|x| (0..self.size()).map(move |y| Coord::new(x, y)))
+                .filter(|outer| self.is_playable(outer))
+                .collect(),
+        }
     }
 
-    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
-        FullCoord::try_new(outer, inner).unwrap()
+    /// The number of moves of `get_moves`, without allocating them.
+    pub fn legal_move_count(&self) -> usize {
+        self.moves().count()
     }
 
-    fn game_with_moves(moves: Vec<((usize, usize), (usize, usize))>) -> Game{
-        let mut game = Game::new();
+    /// What playing `full_coord` would lead to, without changing the game.
+    pub fn move_consequence(&self, full_coord: &FullCoord) -> Result<MoveConsequence, IllegalMove> {
+        self.move_legality(full_coord)?;
 
-        for (outer, inner) in moves {
-            game.play_move(&fc(outer, inner)).unwrap();
-        }
+        let mut after = self.clone();
+        let events = after.play_move(full_coord).unwrap();
 
-        game
+        let inner_result = events.iter().find_map(|event| match event {
+            GameEvent::InnerBoardCompleted(_, result) => Some(result.clone()),
+            _ => None,
+        });
+        let game_result = events.iter().find_map(|event| match event {
+            GameEvent::GameOver(result) => Some(result.clone()),
+            _ => None,
+        });
+
+        Ok(MoveConsequence {
+            full_coord: *full_coord,
+            piece: self.turn,
+            destination: after.active_outer_square,
+            inner_result,
+            game_result,
+        })
     }
 
-    #[test]
-    fn a_fresh_game_should_return_all_squares_as_possible_moves() {
-        let game = Game::new();
-        let moves = game.get_moves();
+    /// The consequences of every legal move, in the order of `get_moves`.
+    pub fn move_consequences(&self) -> Vec<MoveConsequence> {
+        self.moves().map(|full_coord| self.move_consequence(&full_coord).unwrap()).collect()
+    }
 
-        assert_eq!(moves.len(), SIZE * SIZE * SIZE * SIZE);
+    /// Every legal action, the moves of `get_moves` followed by a swap if allowed.
+    pub fn get_actions(&self) -> Vec<Action> {
+        let mut actions: Vec<_> = self.get_moves().into_iter().map(Action::Move).collect();
+        if self.can_swap() {
+            actions.push(Action::Swap);
+        }
+        actions
     }
 
-    #[test]
-    fn for_a_fresh_game_all_outer_squares_should_be_incomplete() {
-        let game = Game::new();
+    pub fn can_swap(&self) -> bool {
+        self.rules.pie_rule == PieRule::SwapAfterFirstMove
+            && !self.swapped
+            && self.state == GameState::InProgress
+            && self.moves_played == 1
+    }
 
-        for x in 0..SIZE {
-            for y in 0..SIZE {
-                assert_eq!(game.get_outer_square(&c(x, y)), OuterSquare::Incomplete);
-            }
+    /// Swaps sides under the pie rule. The board stays as it is and O is
+    /// still to move, only the players behind the pieces change.
+    pub fn swap(&mut self) -> Result<(), String> {
+        if !self.can_swap() {
+            return Err("Swapping sides is only allowed right after the first move".into());
         }
+        self.swapped = true;
+        Ok(())
     }
 
-    #[test]
-    fn playing_a_move_should_change_the_appropriate_square() {
-        let mut game = Game::new();
-        let center = fc((1, 1), (1, 1));
-        game.play_move(&center).unwrap();
+    // Resigning and draw offers are between a player and their one opponent
+    fn opponent(&self, piece: Piece) -> Result<Piece, String> {
+        match piece.opposite() {
+            Some(opponent) if self.rules.players == 2 => Ok(opponent),
+            _ => Err("Only possible in a game of two players".into()),
+        }
+    }
 
-        assert_eq!(game.get_square(&center), Square::Filled(Piece::X));
+    fn end_game(&mut self, state: GameState) -> Result<(), String> {
+        if self.state != GameState::InProgress {
+            return Err("The game is already over".into());
+        }
+        self.state = state;
+        self.active_outer_square = None;
+        self.draw_offer = None;
+        Ok(())
     }
 
-    #[test]
-    fn playing_a_move_should_change_the_turn() {
-        let mut game = Game::new();
-        assert_eq!(game.turn, Piece::X);
+    /// Ends the game with a win for the opponent of `piece`.
+    /// Either player may resign at any time, not just on their turn.
+    /// Only possible with two players, as are `forfeit` and draw offers.
+    pub fn resign(&mut self, piece: Piece) -> Result<(), String> {
+        let winner = self.opponent(piece)?;
+        self.end_game(GameState::GameOver(GameOverResult::Winner(winner, WinReason::Resignation)))
+    }
+
+    /// Ends the game with a win for the opponent of `piece`.
+    pub fn forfeit(&mut self, piece: Piece, reason: ForfeitReason) -> Result<(), String> {
+        let winner = self.opponent(piece)?;
+        self.end_game(GameState::GameOver(GameOverResult::Winner(winner, WinReason::Forfeit(reason))))
+    }
+
+    /// Offers the opponent of `piece` a draw. The offer stands until the
+    /// opponent accepts it or makes a move.
+    pub fn offer_draw(&mut self, piece: Piece) -> Result<(), String> {
+        self.opponent(piece)?;
+        if self.state != GameState::InProgress {
+            return Err("The game is already over".into());
+        }
+        self.draw_offer = Some(piece);
+        Ok(())
+    }
+
+    /// Accepts the draw offered by the opponent of `piece`.
+    pub fn accept_draw(&mut self, piece: Piece) -> Result<(), String> {
+        if self.draw_offer != Some(self.opponent(piece)?) {
+            return Err("There is no draw offer to accept".into());
+        }
+        self.end_game(GameState::AgreedDraw)
+    }
+
+    pub fn play_action(&mut self, action: &Action) -> Result<(), String> {
+        match action {
+            Action::Move(full_coord) => self.play_move(full_coord).map(|_| ()),
+            Action::Swap => self.swap(),
+        }
+    }
+
+    /// Plays a move for the side to move and returns what changed, in the order it happened.
+    pub fn play_move(&mut self, full_coord: &FullCoord) -> Result<Vec<GameEvent>, String> {
+        self.move_legality(full_coord).map_err(|reason| reason.to_string())?;
+        let mut events = vec![GameEvent::PiecePlaced(self.turn, *full_coord)];
+        let previous_active = self.active_outer_square;
+
+        // TODO: why doesn't this work if the variable is inlined?
+        let square = Square::Filled(self.turn);
+        self.set_square(full_coord, square);
+        self.moves_played += 1;
+
+        // Moving instead of accepting declines the opponent's draw offer
+        if let Some(piece) = self.draw_offer {
+            if piece != self.turn {
+                self.draw_offer = None;
+                events.push(GameEvent::DrawOfferDeclined(piece));
+            }
+        }
+
+        self.turn = self.turn.next(self.rules.players);
+
+        let previous_outer = self.get_outer_square(&full_coord.outer);
+        self.update_outer_square(&full_coord.outer);
+        if let (OuterSquare::Incomplete, OuterSquare::Complete(result)) = (previous_outer, self.get_outer_square(&full_coord.outer)) {
+            events.push(GameEvent::InnerBoardCompleted(full_coord.outer, result));
+        }
+
+        // update the active outer square
+        self.active_outer_square = if self.is_playable(&full_coord.inner) {
+            Some(full_coord.inner)
+        } else {
+            None
+        };
+
+        self.update_state();
+
+        if self.active_outer_square != previous_active {
+            events.push(GameEvent::ActiveSquareChanged(self.active_outer_square));
+        }
+        if let GameState::GameOver(ref result) = self.state {
+            events.push(GameEvent::GameOver(result.clone()));
+        }
+
+        Ok(events)
+    }
+
+    // Checks for game over conditions on the inner board
+    // and updates the outer board if necessary
+    fn update_outer_square(&mut self, outer: &Coord) {
+        let outer_index = self.outer_index(outer);
+        if self.outer_board[outer_index] == OuterSquare::Incomplete {
+            if let Some(result) = check_board_result(self.inner_board(outer), &self.rules) {
+                self.outer_board[outer_index] = OuterSquare::Complete(result);
+            } else if self.rules.dead_draws == DeadDrawRule::EndEarly && self.is_dead_board(outer) {
+                self.outer_board[outer_index] = OuterSquare::Complete(GameOverResult::Draw);
+            }
+        }
+    }
+
+    // Checks for game over conditions of the full board
+    fn update_state(&mut self) {
+        if let Some(result) = check_outer_result(&self.outer_board, &self.rules) {
+            self.state = GameState::GameOver(result);
+            self.active_outer_square = None;
+        } else if self.rules.dead_draws == DeadDrawRule::EndEarly && self.is_dead_draw() {
+            self.state = GameState::GameOver(GameOverResult::Draw);
+            self.active_outer_square = None;
+        }
+    }
+}
+
+/// A change made by `Game::play_move`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    PiecePlaced(Piece, FullCoord),
+    /// The given player's draw offer was declined by moving instead.
+    DrawOfferDeclined(Piece),
+    /// The inner board at the given outer square was completed with the given result.
+    InnerBoardCompleted(Coord, GameOverResult),
+    /// Moves now have to be played in the given outer square, or anywhere if `None`.
+    ActiveSquareChanged(Option<Coord>),
+    GameOver(GameOverResult),
+}
+
+/// What a move leads to, see `Game::move_consequence`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveConsequence {
+    pub full_coord: FullCoord,
+    /// The piece making the move.
+    pub piece: Piece,
+    /// The outer square the opponent is sent to, or `None` if they may play
+    /// anywhere or the game is over.
+    pub destination: Option<Coord>,
+    /// How the move completes its inner board, if it does.
+    pub inner_result: Option<GameOverResult>,
+    /// How the move ends the game, if it does.
+    pub game_result: Option<GameOverResult>,
+}
+
+impl MoveConsequence {
+    /// Whether the opponent may play anywhere next, because the move sends them to a completed board.
+    pub fn gives_free_move(&self) -> bool {
+        self.destination.is_none() && self.game_result.is_none()
+    }
+
+    pub fn wins_inner_board(&self) -> bool {
+        matches!(self.inner_result, Some(GameOverResult::Winner(winner, _)) if winner == self.piece)
+    }
+
+    pub fn wins_game(&self) -> bool {
+        matches!(self.game_result, Some(GameOverResult::Winner(winner, _)) if winner == self.piece)
+    }
+}
+
+/// The legal moves of a game, see `Game::moves`.
+pub struct Moves<'a> {
+    game: &'a Game,
+    // Indices into `Game::board` left to look at
+    range: std::ops::Range<usize>,
+}
+
+impl<'a> Iterator for Moves<'a> {
+    type Item = FullCoord;
+
+    fn next(&mut self) -> Option<FullCoord> {
+        let game = self.game;
+        let size = game.size();
+        let inner_count = size * size;
+
+        // Squares are ordered like the moves, see `Game::board`
+        for index in &mut self.range {
+            if game.board[index] != Square::Empty {
+                continue;
+            }
+            // An empty square of a completed board is only playable if play continues inside
+            let outer_index = index / inner_count;
+            if game.outer_board[outer_index] != OuterSquare::Incomplete
+                && game.rules.completed_boards == CompletedBoardRule::FreeMove {
+                continue;
+            }
+
+            let inner_index = index % inner_count;
+            return Some(FullCoord::new(
+                Coord::new(outer_index / size, outer_index % size),
+                Coord::new(inner_index / size, inner_index % size),
+            ));
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.range.len()))
+    }
+}
+
+/// Which way a line runs across a board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Orientation {
+    /// Along a column, with `y` increasing.
+    Column,
+    /// Along a row, with `x` increasing.
+    Row,
+    /// With both `x` and `y` increasing.
+    Diagonal,
+    /// With `x` increasing and `y` decreasing.
+    AntiDiagonal,
+}
+
+impl Orientation {
+    /// Every orientation, in the order lines are looked for.
+    pub const ALL: [Orientation; 4] = [Orientation::Column, Orientation::Row, Orientation::Diagonal, Orientation::AntiDiagonal];
+
+    // The step from one square of a line to the next
+    fn step(self) -> (isize, isize) {
+        match self {
+            Orientation::Column => (0, 1),
+            Orientation::Row => (1, 0),
+            Orientation::Diagonal => (1, 1),
+            Orientation::AntiDiagonal => (1, -1),
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&orientation| orientation == self).unwrap()
+    }
+}
+
+/// Squares in a row on an inner board or on the outer board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Line {
+    // Kept small since every game result holds a few lines
+    first: (u8, u8),
+    orientation: Orientation,
+    length: u8,
+}
+
+impl Line {
+    fn new(first: Coord, orientation: Orientation, length: usize) -> Self {
+        Self { first: (first.x as u8, first.y as u8), orientation, length: length as u8 }
+    }
+
+    /// The line from `first` to `last`, if they're in a row. A single square
+    /// gets the first orientation of `Orientation::ALL`.
+    pub fn between(first: Coord, last: Coord) -> Option<Line> {
+        let length = cmp::max(
+            (last.x as isize - first.x as isize).abs(),
+            (last.y as isize - first.y as isize).abs(),
+        ) as usize + 1;
+        Orientation::ALL.iter()
+            .map(|&orientation| Line::new(first, orientation, length))
+            .find(|line| line.last() == last)
+    }
+
+    pub fn first(&self) -> Coord { Coord::new(self.first.0 as usize, self.first.1 as usize) }
+    pub fn orientation(&self) -> Orientation { self.orientation }
+    pub fn length(&self) -> usize { self.length as usize }
+
+    pub fn last(&self) -> Coord {
+        self.coord_at(self.length as isize - 1)
+    }
+
+    fn coord_at(&self, offset: isize) -> Coord {
+        let (dx, dy) = self.orientation.step();
+        Coord::new((self.first.0 as isize + dx * offset) as usize, (self.first.1 as isize + dy * offset) as usize)
+    }
+
+    /// The squares of the line, from `first` to `last`.
+    pub fn coords(&self) -> impl Iterator<Item = Coord> {
+        let line = *self;
+        (0..self.length as isize).map(move |offset| line.coord_at(offset))
+    }
+
+    pub fn contains(&self, coord: &Coord) -> bool {
+        match self.offset(coord) {
+            Some(offset) => offset >= 0 && offset < self.length as isize,
+            None => false,
+        }
+    }
+
+    // How many steps `coord` is from `first`, if it lies on the extended line at all
+    fn offset(&self, coord: &Coord) -> Option<isize> {
+        let (dx, dy) = self.orientation.step();
+        let (x, y) = (coord.x as isize - self.first.0 as isize, coord.y as isize - self.first.1 as isize);
+        let offset = if dx == 0 { y } else { x / dx };
+        if x == dx * offset && y == dy * offset { Some(offset) } else { None }
+    }
+
+    // The line covering both lines, if they overlap or touch in the same orientation
+    fn join(&self, other: &Line) -> Option<Line> {
+        if other.orientation != self.orientation {
+            return None;
+        }
+        let start = self.offset(&other.first())?;
+        let end = start + other.length as isize;
+        if start > self.length as isize || end < 0 {
+            return None;
+        }
+
+        let new_start = cmp::min(0, start);
+        let new_end = cmp::max(self.length as isize, end);
+        Some(Line::new(self.coord_at(new_start), self.orientation, (new_end - new_start) as usize))
+    }
+}
+
+/// The lines that completed a board.
+///
+/// A move only completes lines through the square played, but a board set
+/// up from a position can hold parallel ones as well. Lines longer than
+/// `RuleSet::win_length` are kept whole.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Lines(Vec<Line>);
+
+impl Lines {
+    /// The first line found, in the order of `Orientation::ALL`, or `None`
+    /// if there are no lines.
+    pub fn first(&self) -> Option<Line> {
+        self.0.first().cloned()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Line> + 'a {
+        self.0.iter().cloned()
+    }
+
+    /// Whether any of the lines contains `coord`.
+    pub fn contains(&self, coord: &Coord) -> bool {
+        self.iter().any(|line| line.contains(coord))
+    }
+}
+
+impl FromIterator<Line> for Lines {
+    /// Joins the lines that overlap, keeping the others as they are, in the
+    /// order of `Orientation::ALL`.
+    fn from_iter<I: IntoIterator<Item = Line>>(lines: I) -> Self {
+        let mut joined: Vec<Line> = vec![];
+        for mut line in lines {
+            // A line can bridge two that didn't touch before
+            while let Some(i) = joined.iter().position(|existing| existing.join(&line).is_some()) {
+                line = joined.remove(i).join(&line).unwrap();
+            }
+            joined.push(line);
+        }
+        joined.sort_by_key(|line| line.orientation.index());
+        Lines(joined)
+    }
+}
+
+impl From<Line> for Lines {
+    fn from(line: Line) -> Self {
+        Some(line).into_iter().collect()
+    }
+}
+
+// Every line of `length` squares in a row on a `size` × `size` board
+fn lines(size: usize, length: usize) -> impl Iterator<Item = Line> {
+    Orientation::ALL.iter().flat_map(move |&orientation| {
+        (0..size).flat_map(move |x| (0..size).filter_map(move |y| {
+            let line = Line::new(Coord::new(x, y), orientation, length);
+            let (dx, dy) = orientation.step();
+            let reach = length as isize - 1;
+            let (end_x, end_y) = (x as isize + dx * reach, y as isize + dy * reach);
+            let in_bounds = |end: isize| end >= 0 && (end as usize) < size;
+            if in_bounds(end_x) && in_bounds(end_y) { Some(line) } else { None }
+        }))
+    })
+}
+
+// Whether `piece` has completed `line`, counting the squares in `taken` as
+// if `piece` had filled them.
+// Boards are square, indexed by x and y from the most to the least significant.
+fn completes_line<T: FilledSquare>(board: &[T], rules: &RuleSet, piece: Piece, line: Line, taken: &[Coord]) -> bool {
+    let square = |coord: Coord| &board[coord.x * rules.size + coord.y];
+
+    // Squares that count for both players can't complete a line on their own
+    line.coords().all(|coord| taken.contains(&coord) || square(coord).counts_for(piece, rules))
+        && line.coords().any(|coord| taken.contains(&coord) || square(coord).filling_piece() == Some(piece))
+}
+
+fn completed_lines<T: FilledSquare>(board: &[T], rules: &RuleSet) -> Vec<(Piece, Line)> {
+    let mut completed = vec![];
+    for line in lines(rules.size, rules.win_length) {
+        for &piece in Piece::all(rules.players) {
+            if completes_line(board, rules, piece, line, &[]) {
+                completed.push((piece, line));
+            }
+        }
+    }
+    completed
+}
+
+// Whether a line could still be completed, given which squares may end up
+// counting towards it and which ones aren't settled yet.
+fn has_open_line<A, U>(rules: &RuleSet, available: A, undecided: U) -> bool
+    where A: Fn(Coord) -> bool, U: Fn(Coord) -> bool
+{
+    lines(rules.size, rules.win_length).any(|line| line.coords().all(&available) && line.coords().any(&undecided))
+}
+
+fn is_full<T: FilledSquare>(board: &[T]) -> bool {
+    board.iter().all(|square| square.is_filled())
+}
+
+// Picks the player with the highest count, if there is exactly one, along
+// with that count and the next highest one.
+fn highest_count<F: Fn(Piece) -> usize>(rules: &RuleSet, count: F) -> Option<(Piece, usize, usize)> {
+    let mut counts: Vec<_> = Piece::all(rules.players).iter().map(|&piece| (piece, count(piece))).collect();
+    counts.sort_by_key(|&(_, count)| cmp::Reverse(count));
+    match (counts[0], counts[1]) {
+        ((piece, highest), (_, next)) if highest > next => Some((piece, highest, next)),
+        _ => None,
+    }
+}
+
+fn check_outer_result(board: &[OuterSquare], rules: &RuleSet) -> Option<GameOverResult> {
+    let misere = rules.misere != Misere::Off;
+    let result = match rules.outer_lines {
+        OuterLineRule::FirstLineWins => check_result(board, rules, misere),
+        OuterLineRule::MostLinesWin => {
+            if !is_full(board) {
+                return None;
+            }
+
+            let lines = completed_lines(board, rules);
+            let count = |piece| lines.iter().filter(|&&(winner, _)| winner == piece).count();
+            // Misère rules are only valid for two players, see `RuleSet::validate`
+            Some(match highest_count(rules, count) {
+                Some((piece, more, fewer)) => match piece.opposite() {
+                    Some(opposite) if misere => GameOverResult::Winner(opposite, WinReason::MostLines(fewer, more)),
+                    _ => GameOverResult::Winner(piece, WinReason::MostLines(more, fewer)),
+                },
+                None => GameOverResult::Draw,
+            })
+        },
+    };
+
+    match (result, rules.tiebreak) {
+        (Some(GameOverResult::Draw), Tiebreak::MostBoards) => {
+            let count = |piece| board.iter()
+                .filter(|square| square.filling_piece() == Some(piece))
+                .count();
+            Some(match highest_count(rules, count) {
+                Some((winner, won, lost)) =>
+                    GameOverResult::Winner(winner, WinReason::MostBoards(won, lost)),
+                None => GameOverResult::Draw,
+            })
+        },
+        (result, _) => result,
+    }
+}
+
+fn check_result<T: FilledSquare>(board: &[T], rules: &RuleSet, misere: bool) -> Option<GameOverResult> {
+    let completed = completed_lines(board, rules);
+    if let Some(&(piece, _)) = completed.first() {
+        let lines = completed.iter()
+            .filter(|&&(owner, _)| owner == piece)
+            .map(|&(_, line)| line)
+            .collect();
+        // Misère rules are only valid for two players, see `RuleSet::validate`
+        return Some(match piece.opposite() {
+            Some(opposite) if misere => GameOverResult::Winner(opposite, WinReason::MisereLine(lines)),
+            _ => GameOverResult::Winner(piece, WinReason::Line(lines)),
+        });
+    }
+
+    // Draw
+    if is_full(board) {
+        Some(GameOverResult::Draw)
+    } else {
+        None
+    }
+}
+
+// The result of a board below the outer board, which only counts misère
+// lines under `Misere::AllBoards`. The nested game completes every level
+// below the top this way.
+fn check_board_result<T: FilledSquare>(board: &[T], rules: &RuleSet) -> Option<GameOverResult> {
+    check_result(board, rules, rules.misere == Misere::AllBoards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(x: usize, y: usize) -> Coord {
+        Coord::try_new(x, y).unwrap()
+    }
+
+    fn ct((x, y): (usize, usize)) -> Coord {
+        Coord::try_new(x, y).unwrap()
+    }
+
+    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
+        FullCoord::try_new(outer, inner).unwrap()
+    }
+
+    fn line(first: (usize, usize), last: (usize, usize)) -> Lines {
+        // Lines may be on boards bigger than `SIZE`
+        let coord = |(x, y)| Coord { x, y };
+        Line::between(coord(first), coord(last)).unwrap().into()
+    }
+
+    fn game_with_moves(moves: Vec<((usize, usize), (usize, usize))>) -> Game{
+        game_with_rules_and_moves(RuleSet::default(), moves)
+    }
+
+    fn game_with_rules_and_moves(rules: RuleSet, moves: Vec<((usize, usize), (usize, usize))>) -> Game{
+        let mut game = Game::new_with_rules(rules);
+
+        for (outer, inner) in moves {
+            game.play_move(&fc(outer, inner)).unwrap();
+        }
+
+        game
+    }
+
+    #[test]
+    fn full_coords_should_round_trip_through_their_text_form() {
+        let full_coord = fc((1, 2), (0, 1));
+
+        assert_eq!(full_coord.to_string(), "1,2:0,1");
+        assert_eq!("1,2:0,1".parse(), Ok(full_coord));
+        assert!("1,2:0".parse::<FullCoord>().is_err());
+        assert!("1,2:0,3".parse::<FullCoord>().is_err());
+        assert_eq!(FullCoord::parse_sized("1,2:0,3", 4), FullCoord::try_new_sized((1, 2), (0, 3), 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "0,5 is off a board of size 3")]
+    fn squares_off_the_board_should_not_be_read_from_another_square() {
+        let game = Game::new();
+        game.get_square(&FullCoord::new(c(0, 0), Coord::new(0, 5)));
+    }
+
+    #[test]
+    fn position_string_should_describe_squares_turn_and_active_square() {
+        let game = game_with_moves(vec![((2, 0), (1, 2))]);
+
+        assert_eq!(
+            game.position_string(),
+            "........./........./.......x./........./........./\
+             ........./........./........./......... o 1,2 1",
+        );
+    }
+
+    #[test]
+    fn a_fresh_game_should_return_all_squares_as_possible_moves() {
+        let game = Game::new();
+        let moves = game.get_moves();
+
+        assert_eq!(moves.len(), SIZE * SIZE * SIZE * SIZE);
+    }
+
+    #[test]
+    fn for_a_fresh_game_all_outer_squares_should_be_incomplete() {
+        let game = Game::new();
+
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                assert_eq!(game.get_outer_square(&c(x, y)), OuterSquare::Incomplete);
+            }
+        }
+    }
+
+    #[test]
+    fn playing_a_move_should_change_the_appropriate_square() {
+        let mut game = Game::new();
+        let center = fc((1, 1), (1, 1));
+        game.play_move(&center).unwrap();
+
+        assert_eq!(game.get_square(&center), Square::Filled(Piece::X));
+    }
+
+    #[test]
+    fn playing_a_move_should_change_the_turn() {
+        let mut game = Game::new();
+        assert_eq!(game.turn, Piece::X);
 
         game.play_move(&fc((1, 1), (1, 1))).unwrap();
         assert_eq!(game.turn, Piece::O);
@@ -445,7 +1632,7 @@ mod tests {
     }
 
     #[test]
-    fn completing_the_game_sets_state_with_winner_and_winning_triple() {
+    fn completing_the_game_sets_state_with_winner_and_winning_line() {
         let game = game_with_moves(vec![
             ((1, 1), (1, 0)),
             ((1, 0), (1, 1)),
@@ -470,12 +1657,789 @@ mod tests {
             ((2, 1), (2, 0)),
         ]);
 
-        let (winner, triple) = match game.state {
-            GameState::GameOver(GameOverResult::Winner(piece, triple)) => (Some(piece), Some(triple)),
+        let (winner, lines) = match game.state {
+            GameState::GameOver(GameOverResult::Winner(piece, WinReason::Line(lines))) => (Some(piece), Some(lines)),
             _ => (None, None),
         };
         assert_eq!(winner, Some(Piece::X));
-        assert_eq!(triple, Some(((0, 1), (1, 1), (2, 1))));
+        assert_eq!(lines, Some(line((0, 1), (2, 1))));
+    }
+
+    fn won(piece: Piece) -> OuterSquare {
+        OuterSquare::Complete(GameOverResult::Winner(piece, WinReason::Line(line((0, 0), (2, 2)))))
+    }
+
+    const DRAWN: OuterSquare = OuterSquare::Complete(GameOverResult::Draw);
+
+    const INCOMPLETE: OuterSquare = OuterSquare::Incomplete;
+
+    const EMPTY_ROW: [OuterSquare; SIZE] = [INCOMPLETE; SIZE];
+
+    const EMPTY: [[OuterSquare; SIZE]; SIZE] = [EMPTY_ROW; SIZE];
+
+    fn row(square: OuterSquare) -> [OuterSquare; SIZE] {
+        [square.clone(), square.clone(), square]
     }
 
+    fn flatten(board: &[[OuterSquare; SIZE]; SIZE]) -> Vec<OuterSquare> {
+        board.iter().flat_map(|column| column.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn a_free_move_can_not_be_played_in_a_completed_outer_square() {
+        let target = (0, 0);
+        let mut game = game_with_moves(vec![
+            (target, (1, 1)),
+            ((1, 1), target),
+            (target, (2, 0)),
+            ((2, 0), target),
+            (target, (0, 2)), // winning target square
+            ((0, 2), target), // back to the target square, which gives a free move
+        ]);
+
+        assert_eq!(game.active_outer_square(), None);
+        assert!(game.play_move(&fc(target, (1, 0))).is_err());
+    }
+
+    #[test]
+    fn with_play_inside_rules_a_completed_square_with_empty_squares_stays_active() {
+        let rules = RuleSet { completed_boards: CompletedBoardRule::PlayInside, ..RuleSet::default() };
+        let target = (0, 0);
+        let mut game = game_with_rules_and_moves(rules, vec![
+            (target, (1, 1)),
+            ((1, 1), target),
+            (target, (2, 0)),
+            ((2, 0), target),
+            (target, (0, 2)), // winning target square
+            ((0, 2), target), // back to the target square
+        ]);
+
+        assert_eq!(game.active_outer_square(), Some(ct(target)));
+        let moves = game.get_moves();
+        assert_eq!(moves.len(), SIZE * SIZE - 3);
+        for move_ in moves {
+            assert_eq!(move_.outer(), ct(target));
+        }
+
+        // Playing inside doesn't change who won the square
+        game.play_move(&fc(target, (1, 0))).unwrap();
+        assert_eq!(game.get_outer_square(&ct(target)), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::X, WinReason::Line(line((0, 2), (2, 0))))));
+    }
+
+    #[test]
+    fn drawn_squares_only_block_lines_when_they_count_for_nobody() {
+        let mut board = EMPTY;
+        board[0][0] = won(Piece::X);
+        board[1][0] = DRAWN;
+        board[2][0] = won(Piece::X);
+
+        assert_eq!(check_outer_result(&flatten(&board), &RuleSet::default()), None);
+
+        let rules = RuleSet { drawn_boards: DrawnBoardRule::CountsForBoth, ..RuleSet::default() };
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Winner(Piece::X, WinReason::Line(line((0, 0), (2, 0))))),
+        );
+    }
+
+    #[test]
+    fn completing_a_line_through_a_drawn_square_wins_when_draws_count_for_both() {
+        let rules = RuleSet { drawn_boards: DrawnBoardRule::CountsForBoth, ..RuleSet::default() };
+        let mut game = Game::new_with_rules(rules);
+        game.outer_board[0] = won(Piece::X);
+        game.outer_board[SIZE] = DRAWN;
+        game.set_square(&fc((2, 0), (0, 0)), Square::Filled(Piece::X));
+        game.set_square(&fc((2, 0), (1, 1)), Square::Filled(Piece::X));
+        game.active_outer_square = Some(c(2, 0));
+
+        game.play_move(&fc((2, 0), (2, 2))).unwrap();
+
+        assert_eq!(
+            game.state(),
+            GameState::GameOver(GameOverResult::Winner(Piece::X, WinReason::Line(line((0, 0), (2, 0))))),
+        );
+    }
+
+    #[test]
+    fn a_line_of_drawn_squares_wins_for_nobody() {
+        let mut board = EMPTY;
+        board[0][2] = DRAWN;
+        board[1][2] = DRAWN;
+        board[2][2] = DRAWN;
+
+        let rules = RuleSet { drawn_boards: DrawnBoardRule::CountsForBoth, ..RuleSet::default() };
+        assert_eq!(check_outer_result(&flatten(&board), &rules), None);
+    }
+
+    #[test]
+    fn with_most_lines_rules_the_game_goes_on_until_the_outer_board_is_full() {
+        let rules = RuleSet { outer_lines: OuterLineRule::MostLinesWin, ..RuleSet::default() };
+        let mut board = EMPTY;
+        board[0] = row(won(Piece::X));
+        board[1] = row(won(Piece::O));
+
+        assert_eq!(check_outer_result(&flatten(&board), &rules), None);
+
+        board[2] = row(won(Piece::X));
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Winner(Piece::X, WinReason::MostLines(2, 1))),
+        );
+
+        board[2] = [won(Piece::X), won(Piece::O), DRAWN];
+        assert_eq!(check_outer_result(&flatten(&board), &rules), Some(GameOverResult::Draw));
+    }
+
+    #[test]
+    fn with_the_board_count_tiebreak_a_full_outer_board_goes_to_the_player_with_more_boards() {
+        let mut board = [
+            [won(Piece::X), won(Piece::O), won(Piece::X)],
+            [won(Piece::X), won(Piece::O), won(Piece::O)],
+            [won(Piece::O), won(Piece::X), DRAWN],
+        ];
+
+        assert_eq!(check_outer_result(&flatten(&board), &RuleSet::default()), Some(GameOverResult::Draw));
+
+        let rules = RuleSet { tiebreak: Tiebreak::MostBoards, ..RuleSet::default() };
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Draw),
+        );
+
+        board[2][2] = won(Piece::X);
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Winner(Piece::X, WinReason::MostBoards(5, 4))),
+        );
+    }
+
+    #[test]
+    fn with_misere_rules_completing_an_outer_line_loses() {
+        let mut board = EMPTY;
+        board[0][0] = won(Piece::X);
+        board[1][1] = won(Piece::X);
+        board[2][2] = won(Piece::X);
+
+        let rules = RuleSet { misere: Misere::OuterBoard, ..RuleSet::default() };
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Winner(Piece::O, WinReason::MisereLine(line((0, 0), (2, 2))))),
+        );
+    }
+
+    #[test]
+    fn with_misere_rules_on_all_boards_completing_an_inner_line_gives_the_board_away() {
+        let target = (0, 0);
+        let moves = vec![
+            (target, (1, 1)),
+            ((1, 1), target),
+            (target, (2, 0)),
+            ((2, 0), target),
+            (target, (0, 2)), // X completes a line in the target square
+        ];
+
+        let rules = RuleSet { misere: Misere::OuterBoard, ..RuleSet::default() };
+        let game = game_with_rules_and_moves(rules, moves.clone());
+        assert_eq!(game.get_outer_square(&ct(target)), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::X, WinReason::Line(line((0, 2), (2, 0))))));
+
+        let rules = RuleSet { misere: Misere::AllBoards, ..RuleSet::default() };
+        let game = game_with_rules_and_moves(rules, moves);
+        assert_eq!(game.get_outer_square(&ct(target)), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::O, WinReason::MisereLine(line((0, 2), (2, 0))))));
+    }
+
+    #[test]
+    fn with_misere_and_most_lines_rules_the_player_with_fewer_lines_wins() {
+        let rules = RuleSet {
+            outer_lines: OuterLineRule::MostLinesWin,
+            misere: Misere::OuterBoard,
+            ..RuleSet::default()
+        };
+        let mut board = EMPTY;
+        board[0] = row(won(Piece::X));
+        board[1] = row(won(Piece::O));
+        board[2] = row(won(Piece::X));
+
+        assert_eq!(
+            check_outer_result(&flatten(&board), &rules),
+            Some(GameOverResult::Winner(Piece::O, WinReason::MostLines(1, 2))),
+        );
+    }
+
+    #[test]
+    fn the_board_count_tiebreak_does_not_end_the_game_early() {
+        let mut board = EMPTY;
+        board[0][0] = won(Piece::X);
+
+        let rules = RuleSet { tiebreak: Tiebreak::MostBoards, ..RuleSet::default() };
+        assert_eq!(check_outer_result(&flatten(&board), &rules), None);
+    }
+
+    #[test]
+    fn lines_should_be_generated_for_any_board_size_and_win_length() {
+        assert_eq!(lines(3, 3).count(), 8);
+        assert_eq!(lines(4, 4).count(), 10);
+        assert_eq!(lines(4, 3).count(), 24);
+
+        let anti_diagonal = Line::between(c(0, 2), c(2, 0)).unwrap();
+        assert_eq!(anti_diagonal.orientation(), Orientation::AntiDiagonal);
+        assert_eq!(anti_diagonal.coords().collect::<Vec<_>>(), vec![c(0, 2), c(1, 1), c(2, 0)]);
+        assert!(anti_diagonal.contains(&c(1, 1)));
+        assert!(!anti_diagonal.contains(&c(0, 0)) && !anti_diagonal.contains(&Coord { x: 3, y: 3 }));
+        assert_eq!(Line::between(c(0, 0), c(1, 2)), None);
+    }
+
+    #[test]
+    fn a_move_completing_two_lines_should_report_both() {
+        let board: Vec<_> = [
+            "xxx", // The column x = 0
+            "oxo",
+            "x.o",
+        ].iter().flat_map(|column| column.chars()).map(|letter| match letter {
+            'x' => Square::Filled(Piece::X),
+            'o' => Square::Filled(Piece::O),
+            _ => Square::Empty,
+        }).collect();
+
+        let lines = match check_result(&board, &RuleSet::default(), false) {
+            Some(GameOverResult::Winner(Piece::X, WinReason::Line(lines))) => lines,
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!(lines.iter().map(|line| line.orientation()).collect::<Vec<_>>(), vec![Orientation::Column, Orientation::AntiDiagonal]);
+        assert_eq!(lines.first(), Line::between(c(0, 0), c(0, 2)));
+        assert!(lines.contains(&c(1, 1)) && lines.contains(&c(2, 0)));
+        assert!(!lines.contains(&c(2, 2)));
+    }
+
+    #[test]
+    fn lines_longer_than_the_win_length_should_be_kept_whole() {
+        let rules = RuleSet { size: 4, win_length: 3, ..RuleSet::default() };
+        // The column x = 0 is the first four squares
+        let mut board = vec![Square::Empty; 16];
+        for square in &mut board[..4] {
+            *square = Square::Filled(Piece::O);
+        }
+
+        match check_result(&board, &rules, false) {
+            Some(GameOverResult::Winner(Piece::O, WinReason::Line(lines))) => {
+                assert_eq!(lines.iter().collect::<Vec<_>>(), vec![Line::between(c(0, 0), Coord { x: 0, y: 3 }).unwrap()]);
+                assert_eq!(lines.first().unwrap().length(), 4);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parallel_lines_should_all_be_kept() {
+        let board: Vec<_> = [
+            "xxx", // The column x = 0
+            "o.o",
+            "xxx",
+        ].iter().flat_map(|column| column.chars()).map(|letter| match letter {
+            'x' => Square::Filled(Piece::X),
+            'o' => Square::Filled(Piece::O),
+            _ => Square::Empty,
+        }).collect();
+
+        let lines = match check_result(&board, &RuleSet::default(), false) {
+            Some(GameOverResult::Winner(Piece::X, WinReason::Line(lines))) => lines,
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!(lines.iter().collect::<Vec<_>>(), vec![
+            Line::between(c(0, 0), c(0, 2)).unwrap(),
+            Line::between(c(2, 0), c(2, 2)).unwrap(),
+        ]);
+        assert!(lines.contains(&c(2, 1)));
+
+        let lines: Lines = Vec::<Line>::new().into_iter().collect();
+        assert_eq!(lines.first(), None);
+    }
+
+    #[test]
+    fn rules_should_reject_a_win_length_longer_than_the_board() {
+        assert!(RuleSet::default().validate().is_ok());
+        assert!(RuleSet { size: 4, win_length: 5, ..RuleSet::default() }.validate().is_err());
+        assert!(RuleSet { size: 0, win_length: 0, ..RuleSet::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn rules_should_reject_lines_of_a_single_square() {
+        assert!(RuleSet { win_length: 1, ..RuleSet::default() }.validate().is_err());
+        assert!(RuleSet { size: 1, win_length: 1, ..RuleSet::default() }.validate().is_err());
+        assert!(RuleSet { size: 2, win_length: 2, ..RuleSet::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn on_bigger_boards_three_in_a_row_can_win_an_inner_board() {
+        let rules = RuleSet { size: 4, win_length: 3, ..RuleSet::default() };
+        let fc4 = |outer, inner| FullCoord::try_new_sized(outer, inner, 4).unwrap();
+        let mut game = Game::new_with_rules(rules);
+        assert_eq!(game.get_moves().len(), 4 * 4 * 4 * 4);
+        assert!(game.play_move(&fc4((3, 3), (3, 3))).is_ok());
+
+        let mut game = Game::new_with_rules(rules);
+        game.set_square(&fc4((3, 1), (1, 1)), Square::Filled(Piece::X));
+        game.set_square(&fc4((3, 1), (2, 2)), Square::Filled(Piece::X));
+        game.active_outer_square = Some(Coord::try_new_sized(3, 1, 4).unwrap());
+
+        game.play_move(&fc4((3, 1), (3, 3))).unwrap();
+
+        assert_eq!(game.state(), GameState::InProgress);
+        assert_eq!(game.get_outer_square(&Coord::try_new_sized(3, 1, 4).unwrap()), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::X, WinReason::Line(line((1, 1), (3, 3))))));
+        assert_eq!(game.active_outer_square(), Some(Coord::try_new_sized(3, 3, 4).unwrap()));
+        assert!(game.play_move(&FullCoord::new(Coord::new(3, 3), Coord::new(4, 0))).is_err());
+    }
+
+    #[test]
+    fn on_bigger_boards_the_outer_line_needs_the_full_win_length() {
+        let rules = RuleSet { size: 4, win_length: 4, ..RuleSet::default() };
+        let mut board = vec![OuterSquare::Incomplete; 4 * 4];
+        for x in 0..3 {
+            board[x * 4 + 3] = won(Piece::O);
+        }
+        assert_eq!(check_outer_result(&board, &rules), None);
+
+        board[3 * 4 + 3] = won(Piece::O);
+        assert_eq!(
+            check_outer_result(&board, &rules),
+            Some(GameOverResult::Winner(Piece::O, WinReason::Line(line((0, 3), (3, 3))))),
+        );
+    }
+
+    #[test]
+    fn actions_should_round_trip_through_their_text_form() {
+        assert_eq!("swap".parse(), Ok(Action::Swap));
+        assert_eq!("1,2:0,1".parse(), Ok(Action::Move(fc((1, 2), (0, 1)))));
+        assert_eq!(Action::Swap.to_string(), "swap");
+    }
+
+    #[test]
+    fn with_the_pie_rule_o_may_swap_only_right_after_the_first_move() {
+        let rules = RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..RuleSet::default() };
+        let mut game = Game::new_with_rules(rules);
+        assert!(!game.can_swap());
+
+        game.play_move(&fc((1, 1), (1, 1))).unwrap();
+        assert!(game.can_swap());
+        assert_eq!(game.get_actions().last(), Some(&Action::Swap));
+
+        game.play_action(&Action::Swap).unwrap();
+        assert!(game.swapped());
+        assert_eq!(game.turn(), Piece::O);
+        assert_eq!(game.active_outer_square(), Some(c(1, 1)));
+        assert!(game.swap().is_err());
+
+        game.play_move(&fc((1, 1), (0, 0))).unwrap();
+        assert!(!game.can_swap());
+    }
+
+    #[test]
+    fn swapping_should_follow_the_moves_played_not_the_pieces_on_the_board() {
+        let rules = RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..RuleSet::default() };
+        let empty_rows = ["........."; 9].join("/");
+
+        // A head start isn't a move, so O may swap after X's first move
+        let mut head_start = Game::from_position_with_rules(rules, &format!("x{} x - 0", &empty_rows[1..])).unwrap();
+        assert!(!head_start.can_swap());
+        head_start.play_move(&fc((1, 1), (1, 1))).unwrap();
+        assert!(head_start.can_swap());
+
+        let swapped = Game::from_position_with_rules(rules, &format!("x{} o - 1 swapped", &empty_rows[1..])).unwrap();
+        assert!(swapped.swapped());
+        assert!(!swapped.can_swap());
+    }
+
+    #[test]
+    fn without_the_pie_rule_swapping_is_not_allowed() {
+        let mut game = game_with_moves(vec![((1, 1), (1, 1))]);
+
+        assert!(!game.get_actions().contains(&Action::Swap));
+        assert!(game.swap().is_err());
+    }
+
+    #[test]
+    fn resigning_should_end_the_game_for_the_opponent() {
+        let mut game = game_with_moves(vec![((1, 1), (1, 1))]);
+
+        game.resign(Piece::X).unwrap();
+
+        assert_eq!(game.state(), GameState::GameOver(GameOverResult::Winner(Piece::O, WinReason::Resignation)));
+        assert!(game.get_moves().is_empty());
+        assert!(game.resign(Piece::O).is_err());
+    }
+
+    #[test]
+    fn a_forfeit_should_record_its_reason() {
+        let mut game = Game::new();
+
+        game.forfeit(Piece::X, ForfeitReason::Timeout).unwrap();
+
+        assert_eq!(game.state(), GameState::GameOver(
+            GameOverResult::Winner(Piece::O, WinReason::Forfeit(ForfeitReason::Timeout))));
+    }
+
+    #[test]
+    fn a_draw_offer_can_be_accepted_until_the_opponent_moves() {
+        let mut game = game_with_moves(vec![((1, 1), (1, 1))]);
+
+        game.offer_draw(Piece::X).unwrap();
+        assert!(game.accept_draw(Piece::X).is_err());
+        game.play_move(&fc((1, 1), (0, 0))).unwrap();
+        assert_eq!(game.draw_offer(), None);
+        assert!(game.accept_draw(Piece::O).is_err());
+
+        game.offer_draw(Piece::X).unwrap();
+        game.accept_draw(Piece::O).unwrap();
+        assert_eq!(game.state(), GameState::AgreedDraw);
+        assert_eq!(game.state().result(), Some(GameOverResult::Draw));
+    }
+
+    #[test]
+    fn a_board_where_every_line_is_blocked_is_dead() {
+        let mut game = Game::new();
+        let (x, o) = (Square::Filled(Piece::X), Square::Filled(Piece::O));
+        let squares = [
+            ((0, 0), x), ((1, 0), o), ((2, 0), x),
+            ((0, 1), x), ((1, 1), o), ((2, 1), o),
+            ((0, 2), o),
+        ];
+        for &(inner, square) in squares.iter() {
+            let index = game.square_index(&fc((0, 0), inner));
+            game.board[index] = square;
+        }
+        game.active_outer_square = Some(c(0, 0));
+        assert!(!game.is_dead_board(&c(0, 0)));
+
+        let mut early = game.clone();
+        early.rules.dead_draws = DeadDrawRule::EndEarly;
+        game.play_move(&fc((0, 0), (1, 2))).unwrap();
+        early.play_move(&fc((0, 0), (1, 2))).unwrap();
+
+        assert!(game.is_dead_board(&c(0, 0)));
+        assert_eq!(game.get_outer_square(&c(0, 0)), OuterSquare::Incomplete);
+        assert_eq!(early.get_outer_square(&c(0, 0)), DRAWN);
+        assert!(!game.is_dead_board(&c(1, 1)));
+    }
+
+    #[test]
+    fn a_game_where_no_outer_line_can_be_completed_is_a_dead_draw() {
+        let (x, o) = (|| won(Piece::X), || won(Piece::O));
+        let mut game = Game::new();
+        game.outer_board = flatten(&[
+            [x(), x(), o()],
+            [o(), o(), x()],
+            [x(), o(), INCOMPLETE],
+        ]);
+        assert!(game.is_dead_draw());
+        assert!(!Game::new().is_dead_draw());
+
+        let mut tiebreak = game.clone();
+        tiebreak.rules.tiebreak = Tiebreak::MostBoards;
+        assert!(!tiebreak.is_dead_draw());
+
+        // A draw counting for both keeps the lines through it open
+        let mut wild = game.clone();
+        wild.rules.drawn_boards = DrawnBoardRule::CountsForBoth;
+        wild.outer_board[0] = DRAWN;
+        assert!(!wild.is_dead_draw());
+
+        game.rules.dead_draws = DeadDrawRule::EndEarly;
+        game.play_move(&fc((2, 2), (1, 1))).unwrap();
+        assert_eq!(game.state(), GameState::GameOver(GameOverResult::Draw));
+    }
+
+    #[test]
+    fn turns_should_cycle_through_every_player() {
+        let rules = RuleSet { size: 4, win_length: 3, players: 3, ..RuleSet::default() };
+        let mut game = Game::new_with_rules(rules);
+        let fc4 = |outer, inner| FullCoord::try_new_sized(outer, inner, 4).unwrap();
+
+        game.play_move(&fc4((0, 0), (1, 1))).unwrap();
+        game.play_move(&fc4((1, 1), (0, 0))).unwrap();
+        assert_eq!(game.turn(), Piece::Triangle);
+        game.play_move(&fc4((0, 0), (3, 3))).unwrap();
+        assert_eq!(game.turn(), Piece::X);
+        assert_eq!(game.get_square(&fc4((0, 0), (3, 3))), Square::Filled(Piece::Triangle));
+    }
+
+    #[test]
+    fn a_third_player_can_win_an_inner_board() {
+        let rules = RuleSet { size: 4, win_length: 3, players: 3, ..RuleSet::default() };
+        let mut game = Game::new_with_rules(rules);
+        let fc4 = |outer, inner| FullCoord::try_new_sized(outer, inner, 4).unwrap();
+        for &inner in &[(0, 0), (1, 0)] {
+            let index = game.square_index(&fc4((0, 0), inner));
+            game.board[index] = Square::Filled(Piece::Triangle);
+        }
+        game.turn = Piece::Triangle;
+
+        game.play_move(&fc4((0, 0), (2, 0))).unwrap();
+
+        assert_eq!(game.get_outer_square(&c(0, 0)), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::Triangle, WinReason::Line(line((0, 0), (2, 0))))));
+        assert!(game.position_string().starts_with("ttt."));
+    }
+
+    #[test]
+    fn two_player_only_rules_should_be_rejected_with_more_players() {
+        let rules = RuleSet { players: 3, ..RuleSet::default() };
+        assert!(rules.validate().is_ok());
+        assert!(RuleSet { players: 1, ..rules }.validate().is_err());
+        assert!(RuleSet { players: 5, ..rules }.validate().is_err());
+        assert!(RuleSet { misere: Misere::OuterBoard, ..rules }.validate().is_err());
+        assert!(RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..rules }.validate().is_err());
+
+        let empty_rows = ["........."; 9].join("/");
+        assert!(Game::from_position_with_rules(rules, &format!("{} x - 0 draw-x", empty_rows)).is_err());
+
+        let mut game = Game::new_with_rules(rules);
+        assert!(game.resign(Piece::X).is_err());
+        assert!(game.offer_draw(Piece::X).is_err());
+        // The pieces of bigger games have no opposite to hand the game to
+        assert!(game.resign(Piece::Triangle).is_err());
+        assert!(game.accept_draw(Piece::Triangle).is_err());
+        assert!(game.forfeit(Piece::Box, ForfeitReason::Timeout).is_err());
+        assert_eq!(game.state(), GameState::InProgress);
+    }
+
+    #[test]
+    fn a_game_set_up_from_its_position_should_play_on_the_same() {
+        let game = game_with_moves(vec![
+            ((0, 0), (1, 1)),
+            ((1, 1), (0, 0)),
+            ((0, 0), (2, 2)),
+            ((2, 2), (0, 0)),
+            ((0, 0), (0, 0)),
+        ]);
+
+        let set_up = Game::from_position(&game.position_string()).unwrap();
+
+        assert_eq!(set_up.position_string(), game.position_string());
+        assert_eq!(set_up.outer_board, game.outer_board);
+        assert_eq!(set_up.turn(), Piece::O);
+        assert_eq!(set_up.get_moves(), game.get_moves());
+
+        let mut offered = game.clone();
+        offered.offer_draw(Piece::X).unwrap();
+        let set_up = Game::from_position(&offered.position_string()).unwrap();
+        assert_eq!(set_up.draw_offer(), Some(Piece::X));
+        assert_eq!(set_up.moves_played(), 5);
+
+        let start = Game::from_position(
+            "........./........./........./........./....x..../........./........./........./......... o 1,1");
+        assert_eq!(start.unwrap().position_string(), game_with_moves(vec![((1, 1), (1, 1))]).position_string());
+    }
+
+    #[test]
+    fn a_handicap_can_give_a_player_a_board_in_advance() {
+        let game = Game::from_position(
+            "ooo....../........./........./........./........./........./........./........./......... x -").unwrap();
+
+        assert_eq!(game.get_outer_square(&c(0, 0)), OuterSquare::Complete(
+            GameOverResult::Winner(Piece::O, WinReason::Line(line((0, 0), (2, 0))))));
+        assert_eq!(game.turn(), Piece::X);
+        assert_eq!(game.get_moves().len(), 72);
+    }
+
+    #[test]
+    fn an_inconsistent_position_should_be_rejected() {
+        let empty_rows = ["........."; 9].join("/");
+        assert!(Game::from_position(&format!("{} x -", empty_rows)).is_ok());
+        assert!(Game::from_position(&format!("{} x", empty_rows)).is_err());
+        assert!(Game::from_position(&format!("{} t -", empty_rows)).is_err());
+        assert!(Game::from_position(&format!("{} x 3,0", empty_rows)).is_err());
+        assert!(Game::from_position(&format!("{} x - none", empty_rows)).is_err());
+        assert!(Game::from_position(&format!("{} x - 0 resigned", empty_rows)).is_err());
+        // Only the pie rule lets the players swap sides
+        assert!(Game::from_position(&format!("{} x - 0 swapped", empty_rows)).is_err());
+        assert!(Game::from_position("........./........./........./........./........./........./........./........ x -").is_err());
+        assert!(Game::from_position(
+            "ooo....../........./........./........./........./........./........./........./......... x 0,0").is_err());
+
+        let rules = RuleSet { players: 3, ..RuleSet::default() };
+        assert!(Game::from_position_with_rules(rules, &format!("{} t -", empty_rows)).is_ok());
+    }
+
+    #[test]
+    fn the_move_iterator_and_legality_checks_should_agree_with_get_moves() {
+        for &completed_boards in &[CompletedBoardRule::FreeMove, CompletedBoardRule::PlayInside] {
+            let mut rng = rng::Rng::new(3);
+            let mut game = Game::new_with_rules(RuleSet { completed_boards, ..RuleSet::default() });
+
+            while game.state() == GameState::InProgress {
+                let moves = game.get_moves();
+                assert_eq!(game.legal_move_count(), moves.len());
+                assert_eq!(game.moves().collect::<Vec<_>>(), moves);
+                for x in 0..SIZE * SIZE {
+                    for y in 0..SIZE * SIZE {
+                        let full_coord = fc((x / SIZE, y / SIZE), (x % SIZE, y % SIZE));
+                        assert_eq!(game.is_legal(&full_coord), moves.contains(&full_coord));
+                    }
+                }
+
+                game.play_move(&moves[rng.below(moves.len())]).unwrap();
+            }
+
+            assert_eq!(game.moves().next(), None);
+            assert_eq!(game.legal_move_count(), 0);
+        }
+    }
+
+    #[test]
+    fn move_legality_should_explain_why_a_move_is_illegal() {
+        let mut game = game_with_moves(vec![((1, 1), (0, 0))]);
+
+        assert_eq!(game.move_legality(&fc((0, 0), (2, 2))), Ok(()));
+        assert_eq!(game.move_legality(&fc((2, 2), (0, 0))), Err(IllegalMove::OutsideActiveSquare(c(0, 0))));
+        assert_eq!(game.move_legality(&FullCoord::new(c(0, 0), Coord::new(3, 0))), Err(IllegalMove::OutOfBounds));
+        assert_eq!(game.playable_outer_squares(), vec![c(0, 0)]);
+
+        game.active_outer_square = None;
+        game.outer_board[0] = won(Piece::O);
+        assert_eq!(game.move_legality(&fc((1, 1), (0, 0))), Err(IllegalMove::Occupied(Piece::X)));
+        assert_eq!(game.move_legality(&fc((0, 0), (2, 2))), Err(IllegalMove::CompletedOuterSquare(
+            GameOverResult::Winner(Piece::O, WinReason::Line(line((0, 0), (2, 2)))))));
+        assert_eq!(game.playable_outer_squares().len(), 8);
+        assert!(!game.playable_outer_squares().contains(&c(0, 0)));
+
+        game.resign(Piece::X).unwrap();
+        assert!(game.playable_outer_squares().is_empty());
+        match game.move_legality(&fc((1, 1), (2, 2))) {
+            Err(IllegalMove::GameOver(_)) => (),
+            legality => panic!("unexpected legality {:?}", legality),
+        }
+    }
+
+    #[test]
+    fn move_consequences_should_tell_where_each_move_sends_the_opponent() {
+        // The game of `completing_the_game_sets_state_with_winner_and_winning_line`, one move short
+        let game = game_with_moves(vec![
+            ((1, 1), (1, 0)),
+            ((1, 0), (1, 1)),
+            ((1, 1), (1, 2)),
+            ((1, 2), (1, 1)),
+            ((1, 1), (1, 1)),
+            ((1, 0), (0, 1)),
+            ((0, 1), (1, 1)),
+            ((0, 0), (0, 1)),
+            ((0, 1), (0, 1)),
+            ((0, 1), (2, 0)),
+            ((2, 0), (1, 1)),
+            ((2, 0), (0, 1)),
+            ((0, 1), (2, 1)),
+            ((2, 1), (0, 1)),
+            ((2, 1), (2, 1)),
+            ((2, 1), (0, 2)),
+            ((0, 2), (0, 1)),
+            ((1, 2), (2, 1)),
+            ((2, 1), (2, 2)),
+            ((2, 2), (2, 1)),
+        ]);
+        let position = game.position_string();
+
+        let consequences = game.move_consequences();
+        assert_eq!(consequences.len(), game.legal_move_count());
+        assert_eq!(game.position_string(), position);
+
+        let sends = consequences.iter().find(|consequence| consequence.full_coord == fc((2, 1), (1, 0))).unwrap();
+        assert_eq!(sends.destination, Some(c(1, 0)));
+        assert!(!sends.gives_free_move() && !sends.wins_inner_board() && !sends.wins_game());
+
+        let free = consequences.iter().find(|consequence| consequence.full_coord == fc((2, 1), (1, 1))).unwrap();
+        assert!(free.gives_free_move());
+
+        let wins = consequences.iter().find(|consequence| consequence.full_coord == fc((2, 1), (2, 0))).unwrap();
+        assert!(wins.wins_inner_board() && wins.wins_game());
+        assert_eq!(wins.destination, None);
+        assert!(!wins.gives_free_move());
+
+        assert!(game.move_consequence(&fc((0, 0), (0, 0))).is_err());
+    }
+
+    #[test]
+    fn playing_a_move_should_report_what_changed() {
+        let mut game = game_with_moves(vec![((1, 1), (2, 2))]);
+        game.offer_draw(Piece::X).unwrap();
+
+        assert_eq!(game.play_move(&fc((2, 2), (0, 0))), Ok(vec![
+            GameEvent::PiecePlaced(Piece::O, fc((2, 2), (0, 0))),
+            GameEvent::DrawOfferDeclined(Piece::X),
+            GameEvent::ActiveSquareChanged(Some(c(0, 0))),
+        ]));
+        // Being sent to the same outer square again changes nothing about it
+        assert_eq!(game.play_move(&fc((0, 0), (0, 0))), Ok(vec![
+            GameEvent::PiecePlaced(Piece::X, fc((0, 0), (0, 0))),
+        ]));
+    }
+
+    #[test]
+    fn winning_the_last_board_should_report_it_before_the_game_over() {
+        let mut game = game_with_moves(vec![
+            ((1, 1), (1, 0)),
+            ((1, 0), (1, 1)),
+            ((1, 1), (1, 2)),
+            ((1, 2), (1, 1)),
+            ((1, 1), (1, 1)),
+            ((1, 0), (0, 1)),
+            ((0, 1), (1, 1)),
+            ((0, 0), (0, 1)),
+            ((0, 1), (0, 1)),
+            ((0, 1), (2, 0)),
+            ((2, 0), (1, 1)),
+            ((2, 0), (0, 1)),
+            ((0, 1), (2, 1)),
+            ((2, 1), (0, 1)),
+            ((2, 1), (2, 1)),
+            ((2, 1), (0, 2)),
+            ((0, 2), (0, 1)),
+            ((1, 2), (2, 1)),
+            ((2, 1), (2, 2)),
+            ((2, 2), (2, 1)),
+        ]);
+
+        let events = game.play_move(&fc((2, 1), (2, 0))).unwrap();
+        let board_result = GameOverResult::Winner(Piece::X, WinReason::Line(line((2, 0), (2, 2))));
+        let game_result = GameOverResult::Winner(Piece::X, WinReason::Line(line((0, 1), (2, 1))));
+        assert_eq!(events, vec![
+            GameEvent::PiecePlaced(Piece::X, fc((2, 1), (2, 0))),
+            GameEvent::InnerBoardCompleted(c(2, 1), board_result),
+            GameEvent::ActiveSquareChanged(None),
+            GameEvent::GameOver(game_result),
+        ]);
+    }
+
+    #[test]
+    fn global_coordinates_should_round_trip() {
+        for size in 1..5 {
+            for index in 0..size * size * size * size {
+                let full_coord = FullCoord::try_from_index(index, size).unwrap();
+                assert_eq!(full_coord.to_index(size), index);
+                assert_eq!(FullCoord::try_from_global(full_coord.to_global(size), size), Ok(full_coord));
+            }
+        }
+
+        assert_eq!(fc((2, 0), (1, 2)).to_global(SIZE), (7, 2));
+        assert_eq!(fc((2, 0), (1, 2)).to_index(SIZE), 25);
+        assert!(FullCoord::try_from_global((9, 0), SIZE).is_err());
+        assert!(FullCoord::try_from_index(81, SIZE).is_err());
+    }
+
+    #[test]
+    fn global_indices_should_follow_the_position_string() {
+        let game = game_with_moves(vec![((2, 0), (1, 2))]);
+        let board: String = game.position_string().split(' ').next().unwrap().split('/').collect();
+
+        assert_eq!(board.find('x'), Some(fc((2, 0), (1, 2)).to_index(SIZE)));
+    }
+
+    #[test]
+    fn neighbours_should_cross_inner_boards_and_wrap_around() {
+        assert_eq!(fc((0, 0), (2, 1)).neighbour(Direction::Right, SIZE), fc((1, 0), (0, 1)));
+        assert_eq!(fc((1, 1), (1, 0)).neighbour(Direction::Up, SIZE), fc((1, 0), (1, 2)));
+        assert_eq!(fc((0, 1), (0, 1)).neighbour(Direction::Left, SIZE), fc((2, 1), (2, 1)));
+        assert_eq!(fc((2, 2), (0, 2)).neighbour(Direction::Down, SIZE), fc((2, 0), (0, 0)));
+    }
 }
@@ -0,0 +1,472 @@
+//! "Ultimate ultimate" tic-tac-toe, with boards nested to any depth.
+//!
+//! At depth 2 this is the regular game. Every cell belongs to a board,
+//! which is itself a cell of a board one level up, all the way to the
+//! top. The path of a move picks the next board at every level: after
+//! playing at `a:b:c` the opponent has to play in board `b:c`, or in as
+//! much of it as is still incomplete.
+//!
+//! `NestedGame` supports a subset of `RuleSet`. Boards are sized by
+//! `size` and `win_length`, `players` take turns as usual, drawn boards
+//! and misère follow the rules as usual, and `outer_lines` and `tiebreak`
+//! decide the top board. Moves sent to a completed board are always free.
+//! Nested games are only ever played to the end: there is no swapping,
+//! resigning or offering draws, no events, and no position string. Rules
+//! that need any of that, `CompletedBoardRule::PlayInside`,
+//! `PieRule::SwapAfterFirstMove` and `DeadDrawRule::EndEarly`, are
+//! rejected.
+//!
+//! Moves are checked and boards completed by the same code as in `Game`,
+//! see `NestedGame::move_legality`. A move outside the active board is
+//! reported against the innermost active board only.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{CompletedBoardRule, Coord, DeadDrawRule, Direction, FullCoord, GameState, IllegalMove, OuterSquare, PieRule, Piece, RuleSet, Square};
+use super::{check_board_result, check_outer_result};
+
+/// The shallowest supported nesting, which is the regular game.
+pub const MIN_DEPTH: usize = 2;
+
+/// The position of a cell, from the top board down to the cell itself.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NestedCoord {
+    path: Vec<Coord>,
+}
+
+impl NestedCoord {
+    pub fn try_new(path: &[(usize, usize)], size: usize) -> Result<Self, String> {
+        let c = Self { path: path.iter().map(|&(x, y)| Coord::new(x, y)).collect() };
+        if c.is_valid_for(size) { Ok(c) } else { Err("Out of bounds".into()) }
+    }
+
+    pub fn is_valid_for(&self, size: usize) -> bool {
+        self.path.iter().all(|coord| coord.is_valid_for(size))
+    }
+
+    /// Like `FullCoord::parse_sized`, with any number of levels.
+    pub fn parse_sized(s: &str, size: usize) -> Result<Self, String> {
+        let parse_coord = |coord: &str| -> Result<(usize, usize), String> {
+            let mut parts = coord.split(',').map(|part| part.trim().parse::<usize>());
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok((x, y)),
+                _ => Err(format!("Malformed coordinate: {:?}", s)),
+            }
+        };
+
+        let path: Result<Vec<_>, _> = s.split(':').map(parse_coord).collect();
+        Self::try_new(&path?, size)
+    }
+
+    pub fn path(&self) -> &[Coord] {
+        &self.path
+    }
+
+    /// The `(col, row)` of the cell on the grid of all cells, like `FullCoord::to_global`.
+    pub fn to_global(&self, size: usize) -> (usize, usize) {
+        self.path.iter().fold((0, 0), |(col, row), coord| (col * size + coord.x, row * size + coord.y))
+    }
+
+    /// The cell at the given `(col, row)` of a game nested `depth` levels deep, see `to_global`.
+    pub fn try_from_global((mut col, mut row): (usize, usize), depth: usize, size: usize) -> Result<Self, String> {
+        let mut path = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            path.push(Coord::new(col % size, row % size));
+            col /= size;
+            row /= size;
+        }
+        if col > 0 || row > 0 {
+            return Err("Out of bounds".into());
+        }
+        path.reverse();
+        Ok(Self { path })
+    }
+
+    /// Like `FullCoord::neighbour`, wrapping around at the edges of the grid of all cells.
+    pub fn neighbour(&self, direction: Direction, size: usize) -> Self {
+        let width = size.pow(self.path.len() as u32);
+        let (col, row) = self.to_global(size);
+        let global = match direction {
+            Direction::Up => (col, (row + width - 1) % width),
+            Direction::Down => (col, (row + 1) % width),
+            Direction::Left => ((col + width - 1) % width, row),
+            Direction::Right => ((col + 1) % width, row),
+        };
+        Self::try_from_global(global, self.path.len(), size).unwrap()
+    }
+}
+
+impl From<FullCoord> for NestedCoord {
+    fn from(full_coord: FullCoord) -> Self {
+        Self { path: vec![full_coord.outer(), full_coord.inner()] }
+    }
+}
+
+impl FromStr for NestedCoord {
+    type Err = String;
+
+    /// Parses the format written by `Display`, for the standard board size.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_sized(s, super::SIZE)
+    }
+}
+
+impl fmt::Display for NestedCoord {
+    /// Formats like `FullCoord`, with one `x,y` per level, for example `1,1:0,2:2,2`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, coord) in self.path.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{},{}", coord.x, coord.y)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NestedGame {
+    rules: RuleSet,
+    depth: usize,
+    state: GameState,
+    turn: Piece,
+    // Every cell, indexed by its path with the top level the most significant
+    squares: Vec<Square>,
+    // `boards[k]` holds the state of the boards `k + 1` levels below the top,
+    // indexed like `squares` by their path
+    boards: Vec<Vec<OuterSquare>>,
+    active_board: Vec<Coord>,
+}
+
+impl NestedGame {
+    pub fn new(depth: usize) -> Self {
+        Self::new_with_rules(depth, RuleSet::default())
+    }
+
+    /// Panics if `depth` is below `MIN_DEPTH` or the rules aren't supported,
+    /// see `try_new_with_rules`.
+    pub fn new_with_rules(depth: usize, rules: RuleSet) -> Self {
+        Self::try_new_with_rules(depth, rules).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Starts a game nested `depth` levels deep, if that depth and the rules
+    /// are supported. The rules have to pass `RuleSet::validate`, and moves
+    /// sent to a completed board are always free.
+    pub fn try_new_with_rules(depth: usize, rules: RuleSet) -> Result<Self, String> {
+        if depth < MIN_DEPTH {
+            return Err(format!("Nesting depth must be at least {}", MIN_DEPTH));
+        }
+        rules.validate().map_err(|err| format!("Invalid rules: {}", err))?;
+        if rules.completed_boards == CompletedBoardRule::PlayInside {
+            return Err("Invalid rules: nested games always give a free move from a completed board".into());
+        }
+        if rules.pie_rule != PieRule::Off {
+            return Err("Invalid rules: nested games don't support swapping sides".into());
+        }
+        if rules.dead_draws != DeadDrawRule::PlayOut {
+            return Err("Invalid rules: nested games are always played out".into());
+        }
+
+        let cells = rules.size * rules.size;
+        Ok(Self {
+            rules,
+            depth,
+            state: GameState::InProgress,
+            turn: Piece::X,
+            squares: vec![Square::Empty; cells.pow(depth as u32)],
+            boards: (1..depth).map(|level| vec![OuterSquare::Incomplete; cells.pow(level as u32)]).collect(),
+            active_board: vec![],
+        })
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state.clone()
+    }
+
+    pub fn turn(&self) -> Piece {
+        self.turn
+    }
+
+    /// The path of the board the next move has to be played in.
+    /// Empty if any cell may be played.
+    pub fn active_board(&self) -> &[Coord] {
+        &self.active_board
+    }
+
+    fn index(&self, path: &[Coord]) -> usize {
+        let size = self.rules.size;
+        path.iter().fold(0, |index, coord| index * size * size + coord.x * size + coord.y)
+    }
+
+    /// Panics if `coord` isn't a cell of this game.
+    pub fn get_square(&self, coord: &NestedCoord) -> Square {
+        assert!(coord.path.len() == self.depth && coord.is_valid_for(self.rules.size), "{} is off the board", coord);
+        self.squares[self.index(&coord.path)]
+    }
+
+    /// The state of the board at `path`, or `None` if `path` doesn't lead to
+    /// a board below the top one. The top board's result is the game's `state`.
+    pub fn get_board(&self, path: &[Coord]) -> Option<OuterSquare> {
+        let leads_to_board = !path.is_empty() && path.len() < self.depth
+            && path.iter().all(|coord| coord.is_valid_for(self.rules.size));
+        if leads_to_board { Some(self.board(path)) } else { None }
+    }
+
+    // Like `get_board`, for a path known to lead to a board below the top one
+    fn board(&self, path: &[Coord]) -> OuterSquare {
+        self.boards[path.len() - 1][self.index(path)].clone()
+    }
+
+    // The first completed board on the way down `path`, if any
+    fn completed_board(&self, path: &[Coord]) -> Option<OuterSquare> {
+        (1..path.len() + 1)
+            .map(|level| self.board(&path[..level]))
+            .find(|board| *board != OuterSquare::Incomplete)
+    }
+
+    fn get_moves_in_board(&self, path: &mut Vec<Coord>, moves: &mut Vec<NestedCoord>) {
+        if path.len() == self.depth {
+            if self.squares[self.index(path)] == Square::Empty {
+                moves.push(NestedCoord { path: path.clone() });
+            }
+            return;
+        }
+        if !path.is_empty() && self.board(path) != OuterSquare::Incomplete {
+            return;
+        }
+
+        for x in 0..self.rules.size {
+            for y in 0..self.rules.size {
+                path.push(Coord::new(x, y));
+                self.get_moves_in_board(path, moves);
+                path.pop();
+            }
+        }
+    }
+
+    pub fn get_moves(&self) -> Vec<NestedCoord> {
+        let mut moves = vec![];
+        if self.state == GameState::InProgress {
+            self.get_moves_in_board(&mut self.active_board.clone(), &mut moves);
+        }
+        moves
+    }
+
+    /// Checks whether `coord` may be played next, like `Game::move_legality`.
+    ///
+    /// A move outside the active board reports the last coordinate of
+    /// `active_board`, and a move in a completed board reports the result of
+    /// the highest completed board it's in.
+    pub fn move_legality(&self, coord: &NestedCoord) -> Result<(), IllegalMove> {
+        if let GameState::GameOver(ref result) = self.state {
+            return Err(IllegalMove::GameOver(result.clone()));
+        }
+
+        let path = &coord.path;
+        if path.len() != self.depth || !coord.is_valid_for(self.rules.size) {
+            return Err(IllegalMove::OutOfBounds);
+        }
+        if let Square::Filled(piece) = self.get_square(coord) {
+            return Err(IllegalMove::Occupied(piece));
+        }
+        if !path.starts_with(&self.active_board) {
+            return Err(IllegalMove::OutsideActiveSquare(*self.active_board.last().unwrap()));
+        }
+        match self.completed_board(&path[..self.depth - 1]) {
+            Some(OuterSquare::Complete(result)) => Err(IllegalMove::CompletedOuterSquare(result)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `coord` may be played next, the same as being one of `get_moves`.
+    pub fn is_legal(&self, coord: &NestedCoord) -> bool {
+        self.move_legality(coord).is_ok()
+    }
+
+    pub fn play_move(&mut self, coord: &NestedCoord) -> Result<(), String> {
+        self.move_legality(coord).map_err(|reason| reason.to_string())?;
+
+        let path = &coord.path;
+        let index = self.index(path);
+        self.squares[index] = Square::Filled(self.turn);
+        self.turn = self.turn.next(self.rules.players);
+
+        // Completing a board can complete the board it's part of, and so on upwards
+        let cells = self.rules.size * self.rules.size;
+        for level in (1..self.depth).rev() {
+            let index = self.index(&path[..level]);
+            let cells_range = index * cells..(index + 1) * cells;
+            let result = if level == self.depth - 1 {
+                check_board_result(&self.squares[cells_range], &self.rules)
+            } else {
+                check_board_result(&self.boards[level][cells_range], &self.rules)
+            };
+
+            match result {
+                Some(result) => self.boards[level - 1][index] = OuterSquare::Complete(result),
+                None => break,
+            }
+        }
+
+        // Send the opponent as deep as possible into the board picked by the move
+        self.active_board.clear();
+        for coord in &path[1..] {
+            self.active_board.push(*coord);
+            if self.board(&self.active_board) != OuterSquare::Incomplete {
+                self.active_board.pop();
+                break;
+            }
+        }
+
+        if let Some(result) = check_outer_result(&self.boards[0], &self.rules) {
+            self.state = GameState::GameOver(result);
+            self.active_board.clear();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::rng::Rng;
+    use {Game, GameOverResult, Line, Lines, WinReason};
+
+    fn nc(path: &[(usize, usize)]) -> NestedCoord {
+        NestedCoord::try_new(path, 3).unwrap()
+    }
+
+    fn c(x: usize, y: usize) -> Coord {
+        Coord::try_new(x, y).unwrap()
+    }
+
+    #[test]
+    fn at_depth_two_it_should_play_exactly_like_the_regular_game() {
+        let mut rng = Rng::new(7);
+        for _ in 0..20 {
+            let mut game = Game::new();
+            let mut nested = NestedGame::new(2);
+
+            while game.state() == GameState::InProgress {
+                let moves = game.get_moves();
+                let nested_moves: Vec<_> = moves.iter().map(|&full_coord| NestedCoord::from(full_coord)).collect();
+                assert_eq!(nested.get_moves(), nested_moves);
+
+                for index in 0..81 {
+                    let full_coord = FullCoord::try_from_index(index, 3).unwrap();
+                    assert_eq!(nested.move_legality(&full_coord.into()), game.move_legality(&full_coord));
+                }
+
+                let full_coord = moves[rng.below(moves.len())];
+                game.play_move(&full_coord).unwrap();
+                nested.play_move(&full_coord.into()).unwrap();
+
+                assert_eq!(nested.state(), game.state());
+                assert_eq!(nested.turn(), game.turn());
+            }
+        }
+    }
+
+    #[test]
+    fn at_depth_three_the_move_picks_the_next_board_at_every_level() {
+        let mut game = NestedGame::new(3);
+        assert_eq!(game.get_moves().len(), 729);
+
+        game.play_move(&nc(&[(0, 0), (1, 1), (2, 0)])).unwrap();
+
+        assert_eq!(game.active_board(), &[c(1, 1), c(2, 0)][..]);
+        let moves = game.get_moves();
+        assert_eq!(moves.len(), 9);
+        assert!(moves.iter().all(|coord| coord.path()[..2] == [c(1, 1), c(2, 0)]));
+        assert!(game.play_move(&nc(&[(0, 0), (1, 1), (2, 1)])).is_err());
+    }
+
+    #[test]
+    fn completing_a_board_should_propagate_to_the_boards_above() {
+        let mut game = NestedGame::new(3);
+        let diagonal: Lines = Line::between(c(0, 0), c(2, 2)).unwrap().into();
+        let won = OuterSquare::Complete(GameOverResult::Winner(Piece::X, WinReason::Line(diagonal.clone())));
+        let index = game.index(&[c(0, 0), c(0, 0)]);
+        game.boards[1][index] = won.clone();
+        let index = game.index(&[c(0, 0), c(1, 1)]);
+        game.boards[1][index] = won;
+        for &inner in &[(0, 0), (1, 1)] {
+            let index = game.index(nc(&[(0, 0), (2, 2), inner]).path());
+            game.squares[index] = Square::Filled(Piece::X);
+        }
+        game.active_board = vec![c(0, 0), c(2, 2)];
+
+        game.play_move(&nc(&[(0, 0), (2, 2), (2, 2)])).unwrap();
+
+        assert_eq!(game.get_board(&[c(0, 0), c(2, 2)]), Some(OuterSquare::Complete(
+            GameOverResult::Winner(Piece::X, WinReason::Line(diagonal.clone())))));
+        assert_eq!(game.get_board(&[c(0, 0)]), Some(OuterSquare::Complete(
+            GameOverResult::Winner(Piece::X, WinReason::Line(diagonal)))));
+        assert_eq!(game.get_board(&[]), None);
+        assert_eq!(game.get_board(&[c(0, 0), c(2, 2), c(2, 2)]), None);
+        assert_eq!(game.active_board(), &[c(2, 2), c(2, 2)][..]);
+        assert_eq!(game.state(), GameState::InProgress);
+    }
+
+    #[test]
+    fn a_move_sent_to_a_completed_board_is_free_within_the_board_above() {
+        let mut game = NestedGame::new(3);
+        let index = game.index(&[c(1, 1), c(2, 0)]);
+        game.boards[1][index] = OuterSquare::Complete(GameOverResult::Draw);
+
+        game.play_move(&nc(&[(0, 0), (1, 1), (2, 0)])).unwrap();
+
+        assert_eq!(game.active_board(), &[c(1, 1)][..]);
+        assert_eq!(game.get_moves().len(), 8 * 9);
+    }
+
+    #[test]
+    fn unsupported_rules_should_be_rejected() {
+        let play_inside = RuleSet { completed_boards: CompletedBoardRule::PlayInside, ..RuleSet::default() };
+        let pie_rule = RuleSet { pie_rule: PieRule::SwapAfterFirstMove, ..RuleSet::default() };
+        let dead_draws = RuleSet { dead_draws: DeadDrawRule::EndEarly, ..RuleSet::default() };
+
+        assert!(NestedGame::try_new_with_rules(3, play_inside).is_err());
+        assert!(NestedGame::try_new_with_rules(3, pie_rule).is_err());
+        assert!(NestedGame::try_new_with_rules(3, dead_draws).is_err());
+        assert!(NestedGame::try_new_with_rules(1, RuleSet::default()).is_err());
+        assert!(NestedGame::try_new_with_rules(3, RuleSet::default()).is_ok());
+    }
+
+    #[test]
+    fn nested_coords_should_match_full_coords_on_the_global_grid() {
+        for index in 0..81 {
+            let full_coord = FullCoord::try_from_index(index, 3).unwrap();
+            let nested = NestedCoord::from(full_coord);
+            assert_eq!(nested.to_global(3), full_coord.to_global(3));
+            assert_eq!(NestedCoord::try_from_global(full_coord.to_global(3), 2, 3), Ok(nested));
+        }
+
+        let coord = nc(&[(2, 0), (1, 2), (0, 1)]);
+        assert_eq!(coord.to_global(3), (21, 7));
+        assert_eq!(NestedCoord::try_from_global((21, 7), 3, 3), Ok(coord.clone()));
+        assert!(NestedCoord::try_from_global((27, 0), 3, 3).is_err());
+        assert_eq!(coord.neighbour(Direction::Left, 3), nc(&[(2, 0), (0, 2), (2, 1)]));
+        assert_eq!(nc(&[(0, 0), (0, 0), (0, 0)]).neighbour(Direction::Up, 3), nc(&[(0, 2), (0, 2), (0, 2)]));
+    }
+
+    #[test]
+    fn nested_coords_should_round_trip_through_their_text_form() {
+        let coord = nc(&[(1, 1), (0, 2), (2, 2)]);
+
+        assert_eq!(coord.to_string(), "1,1:0,2:2,2");
+        assert_eq!("1,1:0,2:2,2".parse(), Ok(coord));
+        assert!("1,1:0,3".parse::<NestedCoord>().is_err());
+        assert!("1,1:0".parse::<NestedCoord>().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Generates suites of balanced opening positions for engine matches.
+//!
+//! Games between engines from `Game::new()` repeat forever, so matches start
+//! from a short random opening instead. Openings that are equivalent by
+//! symmetry are only kept once, and ones the engine already considers
+//! lopsided are left out.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use super::analysis::{self, MoveLabel, SearchLimits};
+use super::rng::Rng;
+use super::{Coord, FullCoord, Game, GameState, RuleSet};
+
+/// Number of rotations and reflections of a square board.
+const SYMMETRIES: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct OpeningSettings {
+    /// How many random moves an opening consists of.
+    pub plies: usize,
+    /// How many openings to look for.
+    pub count: usize,
+    /// How many random openings to try at most while looking for them.
+    pub max_attempts: usize,
+    /// How far the score of the best move may be from even, see `analysis::evaluate`.
+    pub max_score: i32,
+    pub limits: SearchLimits,
+    pub seed: u64,
+    /// The rules the openings are played under. They have to pass `RuleSet::validate`.
+    pub rules: RuleSet,
+}
+
+impl Default for OpeningSettings {
+    fn default() -> Self {
+        Self {
+            plies: 4,
+            count: 20,
+            max_attempts: 1000,
+            max_score: 50,
+            limits: SearchLimits { depth: 3, ..SearchLimits::default() },
+            seed: 1,
+            rules: RuleSet::default(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Opening {
+    pub moves: Vec<FullCoord>,
+    /// The position after `moves`, see `Game::position_string`.
+    pub position: String,
+    /// The score of the best move, from the point of view of the side to move.
+    pub score: i32,
+    /// The rules `moves` were played under. The text form leaves them out.
+    pub rules: RuleSet,
+}
+
+impl Opening {
+    /// The game to start a match from.
+    pub fn game(&self) -> Game {
+        Game::from_position_with_rules(self.rules, &self.position).unwrap()
+    }
+
+    /// Parses the format written by `Display`, checking that the moves lead
+    /// to the position under `rules`. As the rules aren't written, they have
+    /// to be the ones the opening was generated with; openings that can't be
+    /// played under them are rejected.
+    pub fn parse_with_rules(rules: RuleSet, s: &str) -> Result<Self, String> {
+        let mut parts = s.split('|').map(str::trim);
+        let (position, moves, score) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(position), Some(moves), Some(score), None) => (position, moves, score),
+            _ => return Err(format!("Malformed opening: {:?}", s)),
+        };
+
+        rules.validate()?;
+        let moves = moves.split_whitespace()
+            .map(|full_coord| FullCoord::parse_sized(full_coord, rules.size))
+            .collect::<Result<Vec<_>, _>>()?;
+        let score = score.parse().map_err(|_| format!("Malformed score: {:?}", score))?;
+
+        let mut game = Game::new_with_rules(rules);
+        for full_coord in &moves {
+            game.play_move(full_coord)?;
+        }
+        if game.position_string() != Game::from_position_with_rules(rules, position)?.position_string() {
+            return Err(format!("The moves don't lead to the position of {:?}", s));
+        }
+
+        Ok(Self { moves, position: position.into(), score, rules })
+    }
+}
+
+impl fmt::Display for Opening {
+    /// Formats like a `Puzzle`, as `position | moves | score`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} |", self.position)?;
+        for full_coord in &self.moves {
+            write!(f, " {}", full_coord)?;
+        }
+        write!(f, " | {}", self.score)
+    }
+}
+
+impl FromStr for Opening {
+    type Err = String;
+
+    /// Parses an opening of the default rules, see `Opening::parse_with_rules`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with_rules(RuleSet::default(), s)
+    }
+}
+
+/// Reads a suite as written by `generate`, one `Opening` per line.
+/// Blank lines are skipped.
+pub fn parse_suite(text: &str) -> Result<Vec<Opening>, String> {
+    parse_suite_with_rules(RuleSet::default(), text)
+}
+
+/// Like `parse_suite`, for a suite generated under `rules`, see `Opening::parse_with_rules`.
+pub fn parse_suite_with_rules(rules: RuleSet, text: &str) -> Result<Vec<Opening>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Opening::parse_with_rules(rules, line))
+        .collect()
+}
+
+// Rotates `coord` a quarter turn `symmetry % 4` times, after mirroring it
+// for the second half of the symmetries.
+fn transform(coord: Coord, symmetry: usize, size: usize) -> Coord {
+    let (mut x, mut y) = (coord.x, coord.y);
+    if symmetry >= SYMMETRIES / 2 {
+        x = size - 1 - x;
+    }
+    for _ in 0..symmetry % 4 {
+        let rotated = (size - 1 - y, x);
+        x = rotated.0;
+        y = rotated.1;
+    }
+    Coord::new(x, y)
+}
+
+// The same position for all openings that are equivalent by symmetry. The
+// board can be rotated or mirrored as long as every inner board is as well.
+fn canonical_position(moves: &[FullCoord], rules: RuleSet) -> String {
+    (0..SYMMETRIES).map(|symmetry| {
+        let mut game = Game::new_with_rules(rules);
+        let size = game.size();
+        for full_coord in moves {
+            let outer = transform(full_coord.outer(), symmetry, size);
+            let inner = transform(full_coord.inner(), symmetry, size);
+            game.play_move(&FullCoord::new(outer, inner)).unwrap();
+        }
+        game.position_string()
+    }).min().unwrap()
+}
+
+/// Plays random openings according to `settings` and keeps the balanced ones.
+///
+/// Panics if `settings.rules` don't pass `RuleSet::validate`.
+pub fn generate(settings: &OpeningSettings) -> Vec<Opening> {
+    let mut rng = Rng::new(settings.seed);
+    let mut openings = Vec::with_capacity(settings.count);
+    let mut seen = HashSet::new();
+
+    for _ in 0..settings.max_attempts {
+        if openings.len() >= settings.count {
+            break;
+        }
+
+        let mut game = Game::new_with_rules(settings.rules);
+        let mut moves = Vec::with_capacity(settings.plies);
+        while moves.len() < settings.plies && game.state() == GameState::InProgress {
+            let full_coord = game.moves().nth(rng.below(game.legal_move_count())).unwrap();
+            game.play_move(&full_coord).unwrap();
+            moves.push(full_coord);
+        }
+        if game.state() != GameState::InProgress || !seen.insert(canonical_position(&moves, settings.rules)) {
+            continue;
+        }
+
+        let best = analysis::best_move(&game, &settings.limits).unwrap();
+        if best.label == MoveLabel::Unknown && best.score.abs() <= settings.max_score {
+            openings.push(Opening { moves, position: game.position_string(), score: best.score, rules: settings.rules });
+        }
+    }
+
+    openings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fc(outer: (usize, usize), inner: (usize, usize)) -> FullCoord {
+        FullCoord::try_new(outer, inner).unwrap()
+    }
+
+    fn settings() -> OpeningSettings {
+        OpeningSettings {
+            plies: 3,
+            count: 5,
+            limits: SearchLimits { depth: 1, ..SearchLimits::default() },
+            ..OpeningSettings::default()
+        }
+    }
+
+    #[test]
+    fn generated_openings_should_be_balanced_and_unique_up_to_symmetry() {
+        let settings = settings();
+
+        let openings = generate(&settings);
+        assert_eq!(openings.len(), 5);
+
+        let canonical: HashSet<_> = openings.iter().map(|opening| canonical_position(&opening.moves, settings.rules)).collect();
+        assert_eq!(canonical.len(), openings.len());
+        for opening in &openings {
+            assert!(opening.score.abs() <= settings.max_score);
+            assert_eq!(opening.moves.len(), 3);
+            assert_eq!(opening.game().position_string(), opening.position);
+        }
+    }
+
+    #[test]
+    fn rotated_and_mirrored_openings_should_be_the_same() {
+        let opening = [fc((0, 0), (1, 2)), fc((1, 2), (0, 0))];
+        let rotated = [fc((2, 0), (0, 1)), fc((0, 1), (2, 0))];
+        let mirrored = [fc((2, 0), (1, 2)), fc((1, 2), (2, 0))];
+
+        let rules = RuleSet::default();
+
+        assert_eq!(canonical_position(&rotated, rules), canonical_position(&opening, rules));
+        assert_eq!(canonical_position(&mirrored, rules), canonical_position(&opening, rules));
+        assert!(canonical_position(&[fc((1, 1), (1, 1))], rules) != canonical_position(&opening[..1], rules));
+    }
+
+    #[test]
+    fn a_suite_should_read_back_what_was_written() {
+        let openings = generate(&settings());
+        let text: String = openings.iter().map(|opening| format!("{}\n", opening)).collect();
+
+        assert_eq!(parse_suite(&text).unwrap(), openings);
+        assert!(parse_suite("x | 0,0:0,0 | 0").is_err());
+    }
+
+    #[test]
+    fn openings_should_be_generated_and_read_back_under_their_rules() {
+        let rules = RuleSet { size: 4, win_length: 3, ..RuleSet::default() };
+        let settings = OpeningSettings { rules, ..settings() };
+
+        let openings = generate(&settings);
+        assert!(!openings.is_empty());
+        for opening in &openings {
+            assert_eq!(opening.rules, rules);
+            assert_eq!(opening.game().rules(), rules);
+        }
+
+        // The text doesn't say which rules it's for, but a mismatch is caught
+        let text: String = openings.iter().map(|opening| format!("{}\n", opening)).collect();
+        assert_eq!(parse_suite_with_rules(rules, &text).unwrap(), openings);
+        assert!(parse_suite(&text).is_err());
+    }
+}
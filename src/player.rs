@@ -0,0 +1,397 @@
+//! Players and a match runner, including bots that run as external processes.
+//!
+//! External bots speak the CodinGame protocol (see the `codingame` module)
+//! over their stdin and stdout.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::codingame;
+use super::openings::Opening;
+use super::{ForfeitReason, Game, FullCoord, GameOverResult, GameState, Piece};
+
+/// Why a player lost the game without it being decided on the board.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Forfeit {
+    Crash(String),
+    Timeout,
+    /// The reply as the bot sent it, or the move in `FullCoord` notation if
+    /// it could be read but not played.
+    IllegalMove(String),
+}
+
+impl Forfeit {
+    pub fn reason(&self) -> ForfeitReason {
+        match self {
+            Forfeit::Crash(_) => ForfeitReason::Disconnection,
+            Forfeit::Timeout => ForfeitReason::Timeout,
+            Forfeit::IllegalMove(_) => ForfeitReason::IllegalMove,
+        }
+    }
+}
+
+pub trait Player {
+    /// Picks a move for the side to move in `game`.
+    ///
+    /// `last_move` is the opponent's previous move, if any.
+    fn choose_move(&mut self, game: &Game, last_move: Option<FullCoord>) -> Result<FullCoord, Forfeit>;
+}
+
+/// A bot running as a child process.
+pub struct ProcessPlayer {
+    child: Child,
+    stdin: ChildStdin,
+    replies: Receiver<String>,
+    stderr: Arc<Mutex<String>>,
+    stderr_reader: Option<JoinHandle<()>>,
+    move_limit: Duration,
+    time_left: Duration,
+}
+
+impl ProcessPlayer {
+    /// Launches `command` with `args`.
+    ///
+    /// Each reply has to arrive within `move_limit`, and all replies
+    /// together within `total_limit`.
+    pub fn spawn(command: &str, args: &[&str], move_limit: Duration, total_limit: Duration)
+        -> Result<Self, String>
+    {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Could not launch {:?}: {}", command, err))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut child_stderr = child.stderr.take().unwrap();
+
+        // Reading happens on separate threads so that a silent bot
+        // can't block the runner past its time limit.
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let stderr_buffer = stderr.clone();
+        let stderr_reader = thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                match child_stderr.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stderr_buffer.lock().unwrap()
+                        .push_str(&String::from_utf8_lossy(&buffer[..n])),
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            replies,
+            stderr,
+            stderr_reader: Some(stderr_reader),
+            move_limit,
+            time_left: total_limit,
+        })
+    }
+
+    /// Everything the bot has written to stderr so far.
+    pub fn stderr(&self) -> String {
+        self.stderr.lock().unwrap().clone()
+    }
+
+    fn crash(&mut self) -> Forfeit {
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => status.to_string(),
+            _ => "closed its output".into(),
+        };
+        // Make sure everything the bot wrote before dying has been captured.
+        if let Some(reader) = self.stderr_reader.take() {
+            let _ = reader.join();
+        }
+        Forfeit::Crash(status)
+    }
+}
+
+impl Player for ProcessPlayer {
+    fn choose_move(&mut self, game: &Game, last_move: Option<FullCoord>) -> Result<FullCoord, Forfeit> {
+        // Drop replies left over from earlier turns, such as one that came in
+        // after its time ran out, so they aren't taken as the answer to this one.
+        while self.replies.try_recv().is_ok() {}
+
+        let input = codingame::turn_input(game, last_move);
+        if self.stdin.write_all(input.as_bytes()).and_then(|_| self.stdin.flush()).is_err() {
+            return Err(self.crash());
+        }
+
+        let limit = if self.move_limit < self.time_left { self.move_limit } else { self.time_left };
+        let start = Instant::now();
+        let reply = match self.replies.recv_timeout(limit) {
+            Ok(reply) => reply,
+            Err(RecvTimeoutError::Timeout) => return Err(Forfeit::Timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                // Give the process a moment to exit so its status can be reported.
+                let _ = self.child.wait();
+                return Err(self.crash());
+            },
+        };
+        // The reply can arrive just after the limit, which leaves no time at all
+        self.time_left = match self.time_left.checked_sub(start.elapsed()) {
+            Some(time_left) => time_left,
+            None => return Err(Forfeit::Timeout),
+        };
+
+        match codingame::parse_row_col(&reply) {
+            Ok(Some(full_coord)) => Ok(full_coord),
+            _ => Err(Forfeit::IllegalMove(reply)),
+        }
+    }
+}
+
+impl Drop for ProcessPlayer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MatchResult {
+    Finished(GameOverResult),
+    Forfeit(Piece, Forfeit),
+}
+
+/// Plays a full game between `x` and `o`.
+///
+/// Every move is checked with `Game::is_legal`; an illegal one
+/// forfeits the game just like a crash or a timeout does. Forfeits are
+/// recorded in the state of the returned game as well.
+pub fn play_match(x: &mut dyn Player, o: &mut dyn Player) -> (Game, MatchResult) {
+    play_match_from(Game::new(), x, o)
+}
+
+/// Like `play_match`, but continues `game` instead of starting a new one,
+/// for example from an opening.
+///
+/// Panics unless `game` is played by two players.
+pub fn play_match_from(mut game: Game, x: &mut dyn Player, o: &mut dyn Player) -> (Game, MatchResult) {
+    assert!(game.rules().players == 2, "Matches are played by two players, not {}", game.rules().players);
+    let mut last_move = None;
+
+    loop {
+        if let Some(result) = game.state().result() {
+            return (game, MatchResult::Finished(result));
+        }
+
+        let turn = game.turn();
+        let choice = match turn {
+            Piece::X => x.choose_move(&game, last_move),
+            Piece::O => o.choose_move(&game, last_move),
+            piece => unreachable!("{:?} doesn't play in a game of two", piece),
+        };
+
+        let full_coord = match choice {
+            Ok(full_coord) if game.is_legal(&full_coord) => full_coord,
+            Ok(full_coord) => {
+                let forfeit = Forfeit::IllegalMove(full_coord.to_string());
+                return forfeit_match(game, turn, forfeit);
+            },
+            Err(forfeit) => return forfeit_match(game, turn, forfeit),
+        };
+
+        game.play_move(&full_coord).unwrap();
+        last_move = Some(full_coord);
+    }
+}
+
+fn forfeit_match(mut game: Game, piece: Piece, forfeit: Forfeit) -> (Game, MatchResult) {
+    game.forfeit(piece, forfeit.reason()).unwrap();
+    (game, MatchResult::Forfeit(piece, forfeit))
+}
+
+/// The results of a tournament, from the point of view of the first player.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct TournamentScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+/// Plays `games` games between two players, cycling through `openings`.
+///
+/// Every opening is played twice in a row, once with the first player
+/// moving as X and once as O. The players are created anew for every game
+/// by `first` and `second`, which get the opening to start from.
+pub fn play_tournament<A, B>(openings: &[Opening], games: usize, mut first: A, mut second: B)
+    -> Result<TournamentScore, String>
+    where A: FnMut(&Game) -> Result<Box<dyn Player>, String>,
+          B: FnMut(&Game) -> Result<Box<dyn Player>, String>
+{
+    if openings.is_empty() {
+        return Err("A tournament needs at least one opening".into());
+    }
+
+    let mut score = TournamentScore::default();
+    for i in 0..games {
+        let game = openings[(i / 2) % openings.len()].game();
+        let (mut a, mut b) = (first(&game)?, second(&game)?);
+        let first_piece = if i % 2 == 0 { Piece::X } else { Piece::O };
+
+        let (game, _) = if first_piece == Piece::X {
+            play_match_from(game, &mut *a, &mut *b)
+        } else {
+            play_match_from(game, &mut *b, &mut *a)
+        };
+
+        match game.state() {
+            GameState::GameOver(GameOverResult::Winner(winner, _)) if winner == first_piece => score.wins += 1,
+            GameState::GameOver(GameOverResult::Winner(_, _)) => score.losses += 1,
+            _ => score.draws += 1,
+        }
+    }
+    Ok(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::SearchLimits;
+    use openings;
+    use WinReason;
+
+    // Reads a turn and answers with the first valid action.
+    const FIRST_MOVE_BOT: &str = "
+        while read last; do
+            read count
+            read first
+            i=1
+            while [ $i -lt $count ]; do read action; i=$((i + 1)); done
+            echo $first
+        done";
+
+    fn bot(script: &str, move_limit: u64) -> ProcessPlayer {
+        ProcessPlayer::spawn(
+            "sh", &["-c", script],
+            Duration::from_millis(move_limit),
+            Duration::from_secs(60),
+        ).unwrap()
+    }
+
+    #[test]
+    fn two_process_players_should_play_a_full_game() {
+        let mut x = bot(FIRST_MOVE_BOT, 5000);
+        let mut o = bot(FIRST_MOVE_BOT, 5000);
+
+        let (game, result) = play_match(&mut x, &mut o);
+
+        assert_eq!(Some(result), game.state().result().map(MatchResult::Finished));
+    }
+
+    #[test]
+    fn an_illegal_move_should_forfeit_the_game() {
+        let mut x = bot("while read line; do echo 0 0; done", 5000);
+        let mut o = bot(FIRST_MOVE_BOT, 5000);
+
+        let (_, result) = play_match(&mut x, &mut o);
+
+        let illegal = FullCoord::try_new((0, 0), (0, 0)).unwrap();
+        assert_eq!(result, MatchResult::Forfeit(
+            Piece::X, Forfeit::IllegalMove(illegal.to_string())));
+    }
+
+    #[test]
+    fn an_unreadable_reply_should_forfeit_the_game() {
+        let mut x = bot("while read line; do echo center please; done", 5000);
+        let mut o = bot(FIRST_MOVE_BOT, 5000);
+
+        let (game, result) = play_match(&mut x, &mut o);
+
+        assert_eq!(result, MatchResult::Forfeit(Piece::X, Forfeit::IllegalMove("center please".into())));
+        assert_eq!(game.state(), GameState::GameOver(
+            GameOverResult::Winner(Piece::O, WinReason::Forfeit(ForfeitReason::IllegalMove))));
+    }
+
+    #[test]
+    fn a_slow_reply_should_forfeit_the_game() {
+        let mut x = bot("sleep 5", 100);
+        let mut o = bot(FIRST_MOVE_BOT, 5000);
+
+        let (game, result) = play_match(&mut x, &mut o);
+
+        assert_eq!(result, MatchResult::Forfeit(Piece::X, Forfeit::Timeout));
+        assert_eq!(game.state(), GameState::GameOver(
+            GameOverResult::Winner(Piece::O, WinReason::Forfeit(ForfeitReason::Timeout))));
+    }
+
+    #[test]
+    fn a_late_reply_should_not_answer_the_next_turn() {
+        // Answers the first turn with the last action after its time is up
+        let script = format!("
+            read last
+            read count
+            i=0
+            while [ $i -lt $count ]; do read action; i=$((i + 1)); done
+            sleep 0.3
+            echo $action
+            {}", FIRST_MOVE_BOT);
+        let mut x = bot(&script, 100);
+        let game = Game::new();
+
+        assert_eq!(x.choose_move(&game, None), Err(Forfeit::Timeout));
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(x.choose_move(&game, None), Ok(FullCoord::try_new((0, 0), (0, 0)).unwrap()));
+    }
+
+    #[test]
+    fn a_crash_should_forfeit_the_game_and_keep_stderr() {
+        let mut x = bot("read line; echo oops >&2; exit 3", 5000);
+        let mut o = bot(FIRST_MOVE_BOT, 5000);
+
+        let (_, result) = play_match(&mut x, &mut o);
+
+        match result {
+            MatchResult::Forfeit(Piece::X, Forfeit::Crash(_)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(x.stderr(), "oops\n");
+    }
+
+    // Plays the first legal move without an external process.
+    struct FirstMovePlayer;
+
+    impl Player for FirstMovePlayer {
+        fn choose_move(&mut self, game: &Game, _: Option<FullCoord>) -> Result<FullCoord, Forfeit> {
+            Ok(game.get_moves()[0])
+        }
+    }
+
+    #[test]
+    fn a_tournament_should_play_every_opening_with_both_colours() {
+        let openings = openings::generate(&openings::OpeningSettings {
+            count: 2,
+            limits: SearchLimits { depth: 1, ..SearchLimits::default() },
+            ..openings::OpeningSettings::default()
+        });
+        let player = |_: &Game| -> Result<Box<dyn Player>, String> { Ok(Box::new(FirstMovePlayer)) };
+
+        let score = play_tournament(&openings, 4, player, player).unwrap();
+
+        // Identical players win each opening once as X or O, or draw it twice
+        assert_eq!(score.wins + score.draws + score.losses, 4);
+        assert_eq!(score.wins, score.losses);
+        assert!(play_tournament(&[], 1, player, player).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Generates "win in N" puzzles from random or engine games.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::analysis::{self, MoveAnalysis, MoveLabel, SearchLimits, WIN_SCORE};
+use super::rng::Rng;
+use super::{Game, FullCoord, GameState, RuleSet};
+
+// Engine games start with this many random moves, otherwise they would all be the same.
+const RANDOM_OPENING_PLIES: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Playout {
+    Random,
+    Engine(SearchLimits),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PuzzleSettings {
+    /// Length of the forced win, counting the moves of both sides.
+    /// Only odd numbers make sense, as the solver makes the first and last move.
+    pub plies: u32,
+    /// How many puzzles to look for.
+    pub count: usize,
+    /// How many games to play at most while looking for them.
+    pub max_games: usize,
+    pub playout: Playout,
+    pub seed: u64,
+}
+
+impl Default for PuzzleSettings {
+    fn default() -> Self {
+        Self {
+            plies: 3,
+            count: 10,
+            max_games: 1000,
+            playout: Playout::Random,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    /// The rules `position` is played under, which the position string doesn't record.
+    pub rules: RuleSet,
+    pub position: String,
+    pub solution: Vec<FullCoord>,
+    pub difficulty: u32,
+}
+
+impl Puzzle {
+    /// The position to solve, set up from `position`.
+    pub fn game(&self) -> Game {
+        Game::from_position_with_rules(self.rules, &self.position).unwrap()
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} |", self.position)?;
+        for full_coord in &self.solution {
+            write!(f, " {}", full_coord)?;
+        }
+        write!(f, " | {}", self.difficulty)
+    }
+}
+
+// Counts how often the solution line sends the opponent to a completed
+// board, handing out a free move. Those tactics are the hardest to spot.
+fn count_free_moves(game: &Game, solution: &[FullCoord]) -> u32 {
+    let mut game = game.clone();
+    let mut free_moves = 0;
+    for full_coord in solution {
+        game.play_move(full_coord).unwrap();
+        if game.state() == GameState::InProgress && game.active_outer_square().is_none() {
+            free_moves += 1;
+        }
+    }
+    free_moves
+}
+
+// The only move that wins by force, if it takes exactly `plies` plies, and
+// the number of moves there were to choose from.
+fn only_winning_move(game: &Game, plies: u32) -> Option<(MoveAnalysis, usize)> {
+    let limits = SearchLimits { depth: plies, nodes: u64::MAX };
+    let analyses = analysis::analyze(game, &limits);
+    let choices = analyses.len();
+
+    let mut winning = analyses.into_iter().filter(|analysis| analysis.label == MoveLabel::Winning);
+    match (winning.next(), winning.next()) {
+        (Some(solution), None) if solution.score == WIN_SCORE - plies as i32 => Some((solution, choices)),
+        _ => None,
+    }
+}
+
+/// Checks whether the side to move has exactly one move that wins by force,
+/// and whether that win takes exactly `plies` plies.
+///
+/// The solution follows the opponent's best defence, and every later move of
+/// the solver has to be the only winning one as well.
+pub fn find_puzzle(game: &Game, plies: u32) -> Option<Puzzle> {
+    if plies == 0 || game.state() != GameState::InProgress {
+        return None;
+    }
+
+    let (first, choices) = only_winning_move(game, plies)?;
+    let mut solution = vec![];
+    let mut next = first;
+    let mut position = game.clone();
+    let mut remaining = plies;
+    loop {
+        solution.push(next.full_coord);
+        if remaining == 1 {
+            break;
+        }
+        let reply = next.principal_variation[1];
+        solution.push(reply);
+        position.play_move(&next.full_coord).unwrap();
+        position.play_move(&reply).unwrap();
+        remaining -= 2;
+        next = only_winning_move(&position, remaining)?.0;
+    }
+
+    let free_moves = count_free_moves(game, &solution);
+    let difficulty = 100 * plies + 50 * free_moves + choices as u32;
+
+    Some(Puzzle {
+        rules: game.rules(),
+        position: game.position_string(),
+        solution,
+        difficulty,
+    })
+}
+
+fn choose_move(game: &Game, playout: &Playout, rng: &mut Rng, ply: usize) -> FullCoord {
+    match *playout {
+        Playout::Engine(ref limits) if ply >= RANDOM_OPENING_PLIES =>
+            analysis::best_move(game, limits).unwrap().full_coord,
+        _ => game.moves().nth(rng.below(game.legal_move_count())).unwrap(),
+    }
+}
+
+/// Plays games according to `settings` and collects the puzzles found on the way.
+pub fn generate(settings: &PuzzleSettings) -> Vec<Puzzle> {
+    let mut rng = Rng::new(settings.seed);
+    let mut puzzles = Vec::with_capacity(settings.count);
+    let mut seen = HashSet::new();
+
+    for _ in 0..settings.max_games {
+        let mut game = Game::new();
+        let mut ply = 0;
+
+        while game.state() == GameState::InProgress {
+            if puzzles.len() >= settings.count {
+                return puzzles;
+            }
+
+            if let Some(puzzle) = find_puzzle(&game, settings.plies) {
+                if seen.insert(puzzle.position.clone()) {
+                    puzzles.push(puzzle);
+                }
+            }
+
+            let full_coord = choose_move(&game, &settings.playout, &mut rng, ply);
+            game.play_move(&full_coord).unwrap();
+            ply += 1;
+        }
+    }
+
+    puzzles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GameOverResult;
+
+    #[test]
+    fn generated_one_ply_puzzles_should_win_with_their_solution() {
+        let settings = PuzzleSettings { plies: 1, count: 3, ..PuzzleSettings::default() };
+
+        let puzzles = generate(&settings);
+        assert_eq!(puzzles.len(), 3);
+
+        for puzzle in puzzles {
+            let mut game = puzzle.game();
+            let winner = game.turn();
+            assert_eq!(puzzle.solution.len(), 1);
+
+            game.play_move(&puzzle.solution[0]).unwrap();
+            match game.state() {
+                GameState::GameOver(GameOverResult::Winner(piece, _)) => assert_eq!(piece, winner),
+                state => panic!("unexpected state {:?}", state),
+            }
+        }
+    }
+
+    #[test]
+    fn generated_three_ply_puzzles_should_be_rated_and_have_a_unique_win_at_every_move() {
+        let settings = PuzzleSettings { plies: 3, count: 2, ..PuzzleSettings::default() };
+
+        let puzzles = generate(&settings);
+        assert_eq!(puzzles.len(), 2);
+
+        for puzzle in puzzles {
+            let mut game = puzzle.game();
+            let winner = game.turn();
+            assert_eq!(puzzle.solution.len(), 3);
+
+            let free_moves = count_free_moves(&game, &puzzle.solution);
+            assert_eq!(puzzle.difficulty, 300 + 50 * free_moves + game.legal_move_count() as u32);
+
+            // The last move is itself a one-ply puzzle, with the same answer
+            game.play_move(&puzzle.solution[0]).unwrap();
+            game.play_move(&puzzle.solution[1]).unwrap();
+            let last = find_puzzle(&game, 1).unwrap();
+            assert_eq!(last.solution, &puzzle.solution[2..]);
+
+            game.play_move(&puzzle.solution[2]).unwrap();
+            match game.state() {
+                GameState::GameOver(GameOverResult::Winner(piece, _)) => assert_eq!(piece, winner),
+                state => panic!("unexpected state {:?}", state),
+            }
+        }
+    }
+
+    #[test]
+    fn a_move_to_a_completed_board_should_count_as_a_free_move() {
+        let mut rng = Rng::new(1);
+        let mut game = Game::new();
+        loop {
+            let full_coord = game.moves().nth(rng.below(game.legal_move_count())).unwrap();
+            let before = game.clone();
+            game.play_move(&full_coord).unwrap();
+
+            if game.state() != GameState::InProgress {
+                panic!("the game ended without a free move");
+            }
+            if game.active_outer_square().is_none() {
+                assert_eq!(count_free_moves(&before, &[full_coord]), 1);
+                break;
+            }
+            assert_eq!(count_free_moves(&before, &[full_coord]), 0);
+        }
+    }
+
+    #[test]
+    fn generated_puzzles_should_be_unique() {
+        let settings = PuzzleSettings { plies: 1, count: 5, ..PuzzleSettings::default() };
+
+        let puzzles = generate(&settings);
+        let positions: HashSet<_> = puzzles.iter().map(|puzzle| puzzle.position.clone()).collect();
+
+        assert_eq!(positions.len(), puzzles.len());
+    }
+
+    #[test]
+    fn a_fresh_game_is_not_a_puzzle() {
+        assert!(find_puzzle(&Game::new(), 1).is_none());
+    }
+}
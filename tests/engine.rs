@@ -0,0 +1,58 @@
+extern crate ultimate_tic_tac_toe;
+
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::process::{ChildStdout, Command, Stdio};
+
+use ultimate_tic_tac_toe::{Action, Game, GameState};
+
+// Skips `info` lines up to the best move
+fn best_move(stdout: &mut Lines<BufReader<ChildStdout>>) -> (String, usize) {
+    let mut infos = 0;
+    loop {
+        let line = stdout.next().unwrap().unwrap();
+        if let Some(best) = line.strip_prefix("bestmove ") {
+            return (best.to_string(), infos);
+        }
+        assert!(line.starts_with("info depth "), "unexpected line {:?}", line);
+        infos += 1;
+    }
+}
+
+// Plays a whole game against the engine binary, driving it the way a GUI
+// would: set up the position, search, and play the best move it names.
+#[test]
+fn the_engine_binary_should_play_a_full_game_over_its_protocol() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_engine"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    writeln!(stdin, "setoption name Depth value 2\nnewgame\nisready").unwrap();
+    assert_eq!(stdout.next().unwrap().unwrap(), "readyok");
+
+    let mut game = Game::new();
+    let mut actions: Vec<String> = vec![];
+    while game.state() == GameState::InProgress {
+        writeln!(stdin, "position startpos moves {}\ngo nodes 2000", actions.join(" ")).unwrap();
+
+        let (best, infos) = best_move(&mut stdout);
+        assert!(infos > 0);
+        let action: Action = best.parse().unwrap();
+        game.play_action(&action).unwrap();
+        actions.push(best);
+    }
+
+    writeln!(stdin, "position startpos moves {}\ngo depth 1", actions.join(" ")).unwrap();
+    assert_eq!(stdout.next().unwrap().unwrap(), "bestmove none");
+
+    // A long search is cut short by `stop`, and still names a move
+    writeln!(stdin, "newgame\ngo infinite\nstop").unwrap();
+    let (best, _) = best_move(&mut stdout);
+    assert!(best.parse::<Action>().is_ok());
+
+    writeln!(stdin, "quit").unwrap();
+    assert!(child.wait().unwrap().success());
+}
@@ -1,63 +1,47 @@
 extern crate ultimate_tic_tac_toe;
 use ultimate_tic_tac_toe::*;
+use ultimate_tic_tac_toe::nested::{NestedCoord, NestedGame};
 
 mod ui;
-use ui::{UI, Style, Color, Direction};
+use ui::{UI, CompactUI, Style, Color};
 
 const X_COLOR: Color = Color::Red;
 const O_COLOR: Color = Color::Blue;
+const TRIANGLE_COLOR: Color = Color::Green;
+const BOX_COLOR: Color = Color::Yellow;
+const WILD_COLOR: Color = Color::Magenta;
 const SQUARE_HIGHTLIGHT: Color = Color::Cyan;
 
-type CursorPos = (u16, u16);
-
-fn cursorpos_to_game_fullcoord(cursor: &CursorPos) -> FullCoord {
-    let (outer, inner) = cursorpos_to_ui_coord_pair(&cursor);
-    FullCoord::try_new(
-        (outer.0 as usize, outer.1 as usize),
-        (inner.0 as usize, inner.1 as usize),
-    ).unwrap()
+fn piece_color(piece: Piece) -> Color {
+    match piece {
+        Piece::X => X_COLOR,
+        Piece::O => O_COLOR,
+        Piece::Triangle => TRIANGLE_COLOR,
+        Piece::Box => BOX_COLOR,
+    }
 }
 
-fn cursorpos_to_ui_coord_pair(cursor: &CursorPos) -> (ui::Coord, ui::Coord) {
-    let ox = cursor.0 / 3;
-    let ix = cursor.0 % 3;
-    let oy = cursor.1 / 3;
-    let iy = cursor.1 % 3;
-    ((ox, oy), (ix, iy))
+fn ui_coord_pair(coord: &FullCoord) -> (ui::Coord, ui::Coord) {
+    (
+        (coord.outer_x() as u16, coord.outer_y() as u16),
+        (coord.inner_x() as u16, coord.inner_y() as u16),
+    )
 }
 
-fn cursor_highlight(ui: &mut UI, cursor: &CursorPos, player: Piece) {
-    let coords = cursorpos_to_ui_coord_pair(cursor);
-    let style = Style::new().bg(match player {
-        Piece::X => X_COLOR,
-        Piece::O => O_COLOR,
-    });
-    ui.color_inner_square(coords, style);
+fn cursor_highlight(ui: &mut UI, game: &Game, cursor: &FullCoord) {
+    let player = game.turn();
+    let style = Style::new().bg(piece_color(player));
+    ui.color_inner_square(ui_coord_pair(cursor), style);
 }
 
-fn cursor_highlight_clear(ui: &mut UI, cursor: &CursorPos) {
-    let coords = cursorpos_to_ui_coord_pair(cursor);
-    ui.color_inner_square(coords, Style::new().clear_bg());
+fn cursor_highlight_clear(ui: &mut UI, cursor: &FullCoord) {
+    ui.color_inner_square(ui_coord_pair(cursor), Style::new().clear_bg());
 }
 
-fn move_cursor(game: &Game, cursor: &mut CursorPos, dir: Direction) {
+fn move_cursor(game: &Game, cursor: &mut FullCoord, dir: Direction) {
     let original_pos = *cursor;
-    let active_outer_square = game.active_outer_square();
     loop {
-        match dir {
-            Direction::Up => {
-                cursor.1 = if cursor.1 == 0 { 8 } else { cursor.1 - 1 }
-            },
-            Direction::Down => {
-                cursor.1 = if cursor.1 == 8 { 0 } else { cursor.1 + 1 }
-            },
-            Direction::Left => {
-                cursor.0 = if cursor.0 == 0 { 8 } else { cursor.0 - 1 }
-            },
-            Direction::Right => {
-                cursor.0 = if cursor.0 == 8 { 0 } else { cursor.0 + 1 }
-            },
-        }
+        *cursor = cursor.neighbour(dir, game.size());
 
         // We're back at our starting spot,
         // which means there are no valid square in this direction.
@@ -66,110 +50,416 @@ fn move_cursor(game: &Game, cursor: &mut CursorPos, dir: Direction) {
             break;
         }
 
-        let coord = cursorpos_to_game_fullcoord(cursor);
-
-        // This square is in a completed outer square, move one more square
-        if let OuterSquare::Complete(_) = game.get_outer_square(&coord.outer()) {
-            continue;
+        // Stop at the first square that can be played, otherwise move one more square
+        if game.is_legal(cursor) {
+            break;
         }
+    }
+}
+
+fn number_arg(name: &str, value: Option<String>) -> Result<usize, String> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} needs a number", name))
+}
 
-        // This square is outside the active outer square, move one more square
-        if active_outer_square.is_some() && Some(coord.outer()) != active_outer_square {
-            continue;
+enum Mode {
+    Regular(Game),
+    Nested(NestedGame),
+}
+
+fn game_from_args() -> Result<Mode, String> {
+    let mut rules = RuleSet::default();
+    let mut setup = None;
+    let mut depth = None;
+    let mut size = None;
+    let mut win_length = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--draws-count-for-both" => rules.drawn_boards = DrawnBoardRule::CountsForBoth,
+            "--board-count-tiebreak" => rules.tiebreak = Tiebreak::MostBoards,
+            "--misere" => rules.misere = Misere::OuterBoard,
+            "--misere-all-boards" => rules.misere = Misere::AllBoards,
+            "--pie-rule" => rules.pie_rule = PieRule::SwapAfterFirstMove,
+            "--end-dead-draws" => rules.dead_draws = DeadDrawRule::EndEarly,
+            "--size" => size = Some(number_arg(&arg, args.next())?),
+            "--players" => rules.players = number_arg(&arg, args.next())?,
+            "--win-length" => win_length = Some(number_arg(&arg, args.next())?),
+            // Boards nested more than two levels deep, drawn compactly
+            "--depth" => depth = Some(number_arg(&arg, args.next())?),
+            // A position as written by `Game::position_string`, quoted as one argument
+            "--setup" => setup = args.next(),
+            _ => (),
         }
+    }
+
+    // With more players a bigger board with shorter lines keeps wins within reach,
+    // otherwise lines span the whole board unless asked otherwise
+    if rules.players > 2 {
+        rules.size = size.unwrap_or(rules.players + 1);
+        rules.win_length = win_length.unwrap_or(SIZE);
+    } else {
+        rules.size = size.unwrap_or(SIZE);
+        rules.win_length = win_length.unwrap_or(rules.size);
+    }
+    if rules.size < ui::MIN_SIZE as usize || rules.size > ui::MAX_SIZE as usize {
+        return Err(format!("Only board sizes from {} to {} are supported", ui::MIN_SIZE, ui::MAX_SIZE));
+    }
+    rules.validate()?;
+
+    match (depth, setup) {
+        (Some(depth), None) => {
+            if rules.size.checked_pow(depth as u32).is_none_or(|width| width > ui::MAX_COMPACT_WIDTH) {
+                return Err(format!("Nested games are at most {} squares wide", ui::MAX_COMPACT_WIDTH));
+            }
+            NestedGame::try_new_with_rules(depth, rules).map(Mode::Nested)
+        },
+        (Some(_), Some(_)) => Err("Nested games can't be set up from a position".into()),
+        (None, Some(position)) => Game::from_position_with_rules(rules, &position).map(Mode::Regular),
+        (None, None) => Ok(Mode::Regular(Game::new_with_rules(rules))),
+    }
+}
+
+fn draw_square(ui: &mut UI, game: &Game, coord: &FullCoord) {
+    if let Square::Filled(player) = game.get_square(coord) {
+        ui.inner_square(player.symbol(), ui_coord_pair(coord), Style::new().fg(piece_color(player)));
+    }
+}
 
-        // This square is occupied, move one more square
-        if let Square::Filled(_) = game.get_square(&coord) {
-            continue;
+fn draw_outer_square(ui: &mut UI, game: &Game, outer: &Coord) {
+    if let OuterSquare::Complete(result) = game.get_outer_square(outer) {
+        let outer = (outer.x() as u16, outer.y() as u16);
+        let style = Style::new().bg(Color::Black);
+        match result {
+            GameOverResult::Draw if game.rules().drawn_boards == DrawnBoardRule::CountsForBoth =>
+                ui.outer_square_wild(outer, style.fg(WILD_COLOR)),
+            GameOverResult::Draw =>
+                ui.outer_square_draw(outer, style),
+            GameOverResult::Winner(Piece::X, _) =>
+                ui.outer_square_x(outer, style.fg(X_COLOR)),
+            GameOverResult::Winner(Piece::O, _) =>
+                ui.outer_square_o(outer, style.fg(O_COLOR)),
+            GameOverResult::Winner(Piece::Triangle, _) =>
+                ui.outer_square_triangle(outer, style.fg(TRIANGLE_COLOR)),
+            GameOverResult::Winner(Piece::Box, _) =>
+                ui.outer_square_box(outer, style.fg(BOX_COLOR)),
         }
+    }
+}
 
-        // Reached this point so we must be in a valid spot
-        break;
+fn highlight_active_outer_square(ui: &mut UI, game: &Game, active_highlight: &mut Option<ui::Coord>) {
+    // Clear current active outer square highlight
+    if let Some(coord) = *active_highlight {
+        ui.outer_square_grid(coord, Style::new().bg(Color::Black));
+    }
+    *active_highlight = None;
+
+    // Highlight next active outer square
+    if let Some(game_coord) = game.active_outer_square() {
+        let coord = (game_coord.x() as u16, game_coord.y() as u16);
+        ui.outer_square_grid(coord, Style::new().bg(SQUARE_HIGHTLIGHT));
+        *active_highlight = Some(coord);
+    }
+}
+
+fn cursor_to_first_move(ui: &mut UI, game: &Game, cursor: &mut FullCoord) {
+    cursor_highlight_clear(ui, cursor);
+    if let Some(m) = game.moves().next() {
+        *cursor = m;
+        cursor_highlight(ui, game, cursor);
     }
 }
 
 fn main() {
-    let mut game = Game::new();
-    let mut ui = UI::new();
+    match game_from_args() {
+        Ok(Mode::Regular(game)) => play(game),
+        Ok(Mode::Nested(game)) => play_nested(game),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn play(mut game: Game) {
+    let mut ui = UI::new(game.size() as u16);
 
-    let mut cursor: CursorPos = (0, 0);
+    let mut cursor = FullCoord::try_new_sized((0, 0), (0, 0), game.size()).unwrap();
     let mut active_highlight: Option<ui::Coord> = None;
 
     ui.full_render();
 
-    cursor_highlight(&mut ui, &cursor, game.turn());
+    // A game set up from a position may already have squares filled in
+    let size = game.size();
+    for index in 0..size * size * size * size {
+        draw_square(&mut ui, &game, &FullCoord::try_from_index(index, size).unwrap());
+    }
+    for x in 0..size {
+        for y in 0..size {
+            draw_outer_square(&mut ui, &game, &Coord::try_new_sized(x, y, size).unwrap());
+        }
+    }
+    highlight_active_outer_square(&mut ui, &game, &mut active_highlight);
+    cursor_to_first_move(&mut ui, &game, &mut cursor);
+    ui.message(&status(&game, &cursor));
 
     loop {
         match ui.read_input() {
             ui::Input::Exit => break,
+            ui::Input::Error(err) => {
+                ui.message("");
+                println!("Failed to read input: {}", err);
+                break;
+            },
             ui::Input::Move(dir) => {
                 cursor_highlight_clear(&mut ui, &cursor);
                 move_cursor(&game, &mut cursor, dir);
-                cursor_highlight(&mut ui, &cursor, game.turn());
+                cursor_highlight(&mut ui, &game, &cursor);
+                ui.message(&status(&game, &cursor));
+            },
+            ui::Input::Swap => {
+                match game.swap() {
+                    Ok(_) => ui.message("Players swapped sides, the first player now moves as O"),
+                    Err(err) => ui.message(&err),
+                }
             },
             ui::Input::Select => {
-                let (outer, inner) = cursorpos_to_ui_coord_pair(&cursor);
-                let coord = cursorpos_to_game_fullcoord(&cursor);
-
-                if let Ok(_) = game.play_move(&coord) {
-                    // Update the square
-                    let player = game.turn().opposite();
-                    let value = match player {
-                        Piece::X => 'X',
-                        Piece::O => 'O',
-                    };
-                    let style = match player {
-                        Piece::X => Style::new().fg(X_COLOR),
-                        Piece::O => Style::new().fg(O_COLOR),
-                    };
-                    ui.inner_square(value, (outer, inner), style);
-
-                    // Clear current active outer square highlight
-                    if let Some(coord) = active_highlight {
-                        ui.outer_square_grid(coord, Style::new().bg(Color::Black));
-                    }
-                    active_highlight = None;
+                let coord = cursor;
 
-                    // Highlight next active outer square
-                    if let Some(game_coord) = game.active_outer_square() {
-                        let coord = (game_coord.x() as u16, game_coord.y() as u16);
-                        ui.outer_square_grid(coord, Style::new().bg(SQUARE_HIGHTLIGHT));
-                        active_highlight = Some(coord);
-                    }
+                if let Err(reason) = game.move_legality(&coord) {
+                    ui.message(&reason.to_string());
+                    continue;
+                }
 
-                    // Move cursor to first playable move
-                    cursor_highlight_clear(&mut ui, &cursor);
-                    if let Some(m) = game.get_moves().first() {
-                        cursor.0 = (m.outer_x() * 3 + m.inner_x()) as u16;
-                        cursor.1 = (m.outer_y() * 3 + m.inner_y()) as u16;
-                        cursor_highlight(&mut ui, &cursor, game.turn());
+                // Completed boards are drawn last, clearing the highlight
+                // of the previously active square would draw over them
+                let mut completed = vec![];
+                for event in game.play_move(&coord).unwrap() {
+                    match event {
+                        GameEvent::PiecePlaced(_, full_coord) => draw_square(&mut ui, &game, &full_coord),
+                        GameEvent::InnerBoardCompleted(outer, _) => completed.push(outer),
+                        GameEvent::ActiveSquareChanged(_) =>
+                            highlight_active_outer_square(&mut ui, &game, &mut active_highlight),
+                        GameEvent::DrawOfferDeclined(_) | GameEvent::GameOver(_) => (),
                     }
-
-                    // Update completed outer squares
-                    if let OuterSquare::Complete(result) = game.get_outer_square(&coord.outer()) {
-                        let style = Style::new().bg(Color::Black);
-                        match result {
-                            GameOverResult::Draw =>
-                                ui.outer_square_draw(outer, style),
-                            GameOverResult::Winner(Piece::X, _) =>
-                                ui.outer_square_x(outer, style.fg(X_COLOR)),
-                            GameOverResult::Winner(Piece::O, _) =>
-                                ui.outer_square_o(outer, style.fg(O_COLOR)),
-                        }
+                }
+                for outer in completed {
+                    draw_outer_square(&mut ui, &game, &outer);
+                }
+                cursor_to_first_move(&mut ui, &game, &mut cursor);
+                ui.message(&status(&game, &cursor));
+            },
+            ui::Input::Resign => {
+                // Any other key takes it back, so a stray key press doesn't end the game
+                ui.message("Press r again to resign, any other key to keep playing");
+                match ui.read_input() {
+                    ui::Input::Resign => if let Err(err) = game.resign(game.turn()) {
+                        ui.message(&err);
+                    },
+                    _ => ui.message(&status(&game, &cursor)),
+                }
+            },
+            ui::Input::Draw => {
+                let player = game.turn();
+                if game.accept_draw(player).is_err() {
+                    match game.offer_draw(player) {
+                        Ok(_) => ui.message(&prompt(&game)),
+                        Err(err) => ui.message(&err),
                     }
+                }
+            },
+            _ => (),
+        }
 
-                    // Check for game over
-                    if let GameState::GameOver(result) = game.state() {
-                        ui.reset_cursor_position();
-                        println!("Game Over: {}", match result {
-                            GameOverResult::Draw => "It was a draw!".into(),
-                            GameOverResult::Winner(winner, _) => format!("{:?} Won", winner),
-                        });
-                        break;
-                    }
+        // Check for game over
+        let message = match game.state() {
+            GameState::InProgress => continue,
+            GameState::AgreedDraw => "Both players agreed to a draw".into(),
+            GameState::GameOver(result) => game_over_message(result),
+        };
+        ui.message("");
+        println!("Game Over: {}", message);
+        break;
+    }
+}
+
+// The first completed board on the way down to `coord`, if any
+fn completed_nested_board(game: &NestedGame, coord: &NestedCoord) -> Option<GameOverResult> {
+    let path = coord.path();
+    (1..path.len()).filter_map(|level| match game.get_board(&path[..level]) {
+        Some(OuterSquare::Complete(result)) => Some(result),
+        _ => None,
+    }).next()
+}
+
+fn draw_nested_square(ui: &CompactUI, game: &NestedGame, coord: &NestedCoord, cursor: &NestedCoord) {
+    let size = game.rules().size;
+    let (col, row) = coord.to_global(size);
+    let value = match game.get_square(coord) {
+        Square::Filled(player) => player.symbol(),
+        Square::Empty => '·',
+    };
+
+    // Completed boards are filled in with the winner's color, the cursor and the
+    // board to play in are highlighted like on the regular board
+    let style = match completed_nested_board(game, coord) {
+        Some(GameOverResult::Winner(winner, _)) => Style::new().fg(Color::Black).bg(piece_color(winner)),
+        Some(_) => Style::new().fg(Color::Black).bg(Color::Grey),
+        None if coord == cursor => Style::new().bg(piece_color(game.turn())),
+        None if !game.active_board().is_empty() && coord.path().starts_with(game.active_board()) =>
+            Style::new().bg(SQUARE_HIGHTLIGHT),
+        None => match game.get_square(coord) {
+            Square::Filled(player) => Style::new().fg(piece_color(player)),
+            Square::Empty => Style::new(),
+        },
+    };
+    ui.square(value, (col as u16, row as u16), style);
+}
+
+fn draw_nested_game(ui: &CompactUI, game: &NestedGame, cursor: &NestedCoord) {
+    let size = game.rules().size;
+    let width = size.pow(game.depth() as u32);
+    for col in 0..width {
+        for row in 0..width {
+            let coord = NestedCoord::try_from_global((col, row), game.depth(), size).unwrap();
+            draw_nested_square(ui, game, &coord, cursor);
+        }
+    }
+}
+
+fn move_nested_cursor(game: &NestedGame, cursor: &mut NestedCoord, dir: Direction) {
+    let original_pos = cursor.clone();
+    loop {
+        *cursor = cursor.neighbour(dir, game.rules().size);
+
+        // Like `move_cursor`, give up once back at the starting spot
+        if *cursor == original_pos || game.is_legal(cursor) {
+            break;
+        }
+    }
+}
+
+fn play_nested(mut game: NestedGame) {
+    let ui = CompactUI::new(game.rules().size as u16, game.depth() as u16);
+    let mut cursor = game.get_moves()[0].clone();
+
+    ui.full_render();
+    draw_nested_game(&ui, &game, &cursor);
+
+    loop {
+        match ui.read_input() {
+            ui::Input::Exit => break,
+            ui::Input::Error(err) => {
+                ui.message("");
+                println!("Failed to read input: {}", err);
+                break;
+            },
+            ui::Input::Move(dir) => {
+                move_nested_cursor(&game, &mut cursor, dir);
+                draw_nested_game(&ui, &game, &cursor);
+                ui.message("");
+            },
+            ui::Input::Select => {
+                if let Err(reason) = game.move_legality(&cursor) {
+                    ui.message(&reason.to_string());
+                    continue;
                 }
+
+                game.play_move(&cursor).unwrap();
+                if let Some(m) = game.get_moves().first() {
+                    cursor = m.clone();
+                }
+                draw_nested_game(&ui, &game, &cursor);
+                ui.message("");
             },
+            ui::Input::Swap | ui::Input::Resign | ui::Input::Draw =>
+                ui.message("Nested games can only be played to the end"),
             _ => (),
         }
+
+        if let GameState::GameOver(result) = game.state() {
+            ui.message("");
+            println!("Game Over: {}", game_over_message(result));
+            break;
+        }
+    }
+}
+
+// What the player to move can do besides moving
+fn prompt(game: &Game) -> String {
+    if game.can_swap() {
+        "O may press p to swap sides".into()
+    } else if let Some(piece) = game.draw_offer() {
+        format!("{:?} offers a draw, press o to accept", piece)
+    } else {
+        "".into()
+    }
+}
+
+// What playing the square under the cursor would lead to
+fn move_hint(game: &Game, cursor: &FullCoord) -> String {
+    let consequence = match game.move_consequence(cursor) {
+        Ok(consequence) => consequence,
+        Err(_) => return "".into(),
+    };
+    let next = consequence.piece.next(game.rules().players);
+
+    if consequence.wins_game() {
+        return "Wins the game".into();
+    } else if consequence.game_result.is_some() {
+        return "Ends the game".into();
+    }
+
+    let prefix = if consequence.wins_inner_board() { "Wins the board, " } else { "" };
+    let destination = match consequence.destination {
+        Some(destination) => destination,
+        None => return format!("{}{:?} may play anywhere", prefix, next),
+    };
+
+    // Warn about sending the opponent to a board they can win right away,
+    // unless misère rules make completing lines a bad idea
+    let rules = game.rules();
+    let mut after = game.clone();
+    after.play_move(&consequence.full_coord).unwrap();
+    let warning = if rules.misere == Misere::AllBoards
+        || analysis::inner_threats(&after, &destination, next).squares.is_empty() {
+        ""
+    } else if rules.misere == Misere::Off && rules.outer_lines == OuterLineRule::FirstLineWins
+        && analysis::outer_threats(&after, next).squares.contains(&destination) {
+        ", where they can win the game"
+    } else {
+        ", where they can win the board"
+    };
+    format!("{}{:?} must play in {},{}{}", prefix, next, destination.x(), destination.y(), warning)
+}
+
+// The prompt followed by the hint for the square under the cursor
+fn status(game: &Game, cursor: &FullCoord) -> String {
+    let (prompt, hint) = (prompt(game), move_hint(game, cursor));
+    match (prompt.is_empty(), hint.is_empty()) {
+        (false, false) => format!("{}. {}", prompt, hint),
+        (true, _) => hint,
+        (false, true) => prompt,
+    }
+}
+
+fn game_over_message(result: GameOverResult) -> String {
+    // Misère lines, resigning and forfeits only happen in games of two players
+    let loser = |winner: Piece| winner.opposite()
+        .map_or_else(|| "their opponent".to_string(), |loser| format!("{:?}", loser));
+    match result {
+        GameOverResult::Draw => "It was a draw!".into(),
+        GameOverResult::Winner(winner, WinReason::Line(_)) =>
+            format!("{:?} Won", winner),
+        GameOverResult::Winner(winner, WinReason::MisereLine(_)) =>
+            format!("{:?} Won, {} completed a line", winner, loser(winner)),
+        GameOverResult::Winner(winner, WinReason::MostLines(won, lost)) =>
+            format!("{:?} Won on lines, {} to {}", winner, won, lost),
+        GameOverResult::Winner(winner, WinReason::MostBoards(won, lost)) =>
+            format!("{:?} Won on board count, {} to {}", winner, won, lost),
+        GameOverResult::Winner(winner, WinReason::Resignation) =>
+            format!("{:?} Won, {} resigned", winner, loser(winner)),
+        GameOverResult::Winner(winner, WinReason::Forfeit(reason)) =>
+            format!("{:?} Won, {} forfeited ({:?})", winner, loser(winner), reason),
     }
 }
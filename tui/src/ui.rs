@@ -10,46 +10,16 @@ use self::crossterm::terminal::{terminal, ClearType};
 
 pub use self::crossterm::style::Color;
 
+use ultimate_tic_tac_toe::Direction;
+
 const OUTER_GRID_OFFSET: (u16, u16) = (2, 1);
 const OUTER_GRID_SQUARE_OFFSET: (u16, u16) = (2, 1);
 
-const FULL_RENDER_HEIGHT: u16 = 24;
-
-const INITIAL_RENDER: &'static str = "
-                 ██               ██
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-                 ██               ██
-  █████████████████████████████████████████████████
-                 ██               ██
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-                 ██               ██
-  █████████████████████████████████████████████████
-                 ██               ██
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-    ───┼───┼───  ██  ───┼───┼───  ██  ───┼───┼───
-       │   │     ██     │   │     ██     │   │
-                 ██               ██";
+/// The smallest and largest board sizes that fit the renders and a terminal.
+pub const MIN_SIZE: u16 = 3;
+pub const MAX_SIZE: u16 = 5;
 
 type InnerRender = [&'static str; 7];
-const INNER_GRID_RENDER: InnerRender = [
-    "               ",
-    "     │   │     ",
-    "  ───┼───┼───  ",
-    "     │   │     ",
-    "  ───┼───┼───  ",
-    "     │   │     ",
-    "               ",
-];
 const INNER_X_RENDER: InnerRender = [
     "               ",
     "   ██     ██   ",
@@ -68,6 +38,24 @@ const INNER_O_RENDER: InnerRender = [
     "     █████     ",
     "               ",
 ];
+const INNER_TRIANGLE_RENDER: InnerRender = [
+    "               ",
+    "       █       ",
+    "      ███      ",
+    "     ██ ██     ",
+    "    ██   ██    ",
+    "   █████████   ",
+    "               ",
+];
+const INNER_BOX_RENDER: InnerRender = [
+    "               ",
+    "   █████████   ",
+    "   ██     ██   ",
+    "   ██     ██   ",
+    "   ██     ██   ",
+    "   █████████   ",
+    "               ",
+];
 const INNER_DRAW_RENDER: InnerRender = [
     "               ",
     "               ",
@@ -77,53 +65,120 @@ const INNER_DRAW_RENDER: InnerRender = [
     "               ",
     "               ",
 ];
+const INNER_WILD_RENDER: InnerRender = [
+    "               ",
+    "      ███      ",
+    "      ███      ",
+    "   █████████   ",
+    "      ███      ",
+    "      ███      ",
+    "               ",
+];
 
 pub type Coord = (u16, u16);
 
-fn outer_square_coord_to_pos((x, y): Coord) -> Coord {
-    (x * 17 + OUTER_GRID_OFFSET.0, y * 8 + OUTER_GRID_OFFSET.1)
+// An inner grid is 4 characters wide and 2 lines high per square, plus a border
+fn inner_render_width(size: u16) -> u16 {
+    size * 4 + 3
 }
 
-fn inner_square_coord_to_pos(((ox, oy), (ix, iy)): (Coord, Coord)) -> Coord {
-    let (ox, oy) = outer_square_coord_to_pos((ox, oy));
-    (ox + ix * 4 + 1 + OUTER_GRID_SQUARE_OFFSET.0,
-        oy + iy * 2 + OUTER_GRID_SQUARE_OFFSET.1)
+fn inner_render_height(size: u16) -> u16 {
+    size * 2 + 1
+}
+
+fn inner_grid_render(size: u16) -> Vec<String> {
+    let cells = vec!["   "; size as usize].join("│");
+    let separator = vec!["───"; size as usize].join("┼");
+    let blank = " ".repeat(inner_render_width(size) as usize);
+
+    let mut render = vec![blank.clone()];
+    for row in 0..size {
+        if row > 0 {
+            render.push(format!("  {}  ", separator));
+        }
+        render.push(format!("  {}  ", cells));
+    }
+    render.push(blank);
+    render
+}
+
+// Centers one of the fixed size renders on an inner grid of the given size
+fn fit_render(render: &InnerRender, size: u16) -> Vec<String> {
+    let padding_x = (inner_render_width(size) - inner_render_width(MIN_SIZE)) as usize / 2;
+    let padding_y = (inner_render_height(size) - inner_render_height(MIN_SIZE)) / 2;
+    let blank = " ".repeat(inner_render_width(size) as usize);
+
+    let mut lines = vec![blank.clone(); padding_y as usize];
+    lines.extend(render.iter().map(|line| format!("{0}{1}{0}", " ".repeat(padding_x), line)));
+    lines.extend(vec![blank; padding_y as usize]);
+    lines
+}
+
+fn initial_render(size: u16) -> String {
+    let inner = inner_grid_render(size);
+    let row_separator = "█".repeat((size * inner_render_width(size) + (size - 1) * 2) as usize);
+
+    let mut render = String::new();
+    for oy in 0..size {
+        if oy > 0 {
+            render.push_str(&format!("\n  {}", row_separator));
+        }
+        for line in &inner {
+            render.push_str(&format!("\n  {}", vec![&line[..]; size as usize].join("██")));
+        }
+    }
+    render
 }
 
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+fn outer_square_coord_to_pos(size: u16, (x, y): Coord) -> Coord {
+    (x * (inner_render_width(size) + 2) + OUTER_GRID_OFFSET.0,
+        y * (inner_render_height(size) + 1) + OUTER_GRID_OFFSET.1)
+}
+
+fn inner_square_coord_to_pos(size: u16, ((ox, oy), (ix, iy)): (Coord, Coord)) -> Coord {
+    let (ox, oy) = outer_square_coord_to_pos(size, (ox, oy));
+    (ox + ix * 4 + 1 + OUTER_GRID_SQUARE_OFFSET.0,
+        oy + iy * 2 + OUTER_GRID_SQUARE_OFFSET.1)
 }
 
 pub enum Input {
     Exit,
     Invalid,
     Select,
+    Swap,
+    Resign,
+    Draw,
     Move(Direction),
     Error(io::Error),
 }
 
 pub struct UI {
     screen: Screen,
-    square_vals: [[[[char; 3]; 3]; 3]; 3],
-    square_fgs: [[[[Color; 3]; 3]; 3]; 3],
-    square_bgs: [[[[Color; 3]; 3]; 3]; 3],
-    outer_square_bgs: [[Color; 3]; 3],
+    size: u16,
+    square_vals: Vec<char>,
+    square_fgs: Vec<Color>,
+    square_bgs: Vec<Color>,
+    outer_square_bgs: Vec<Color>,
 }
 
 impl UI {
-    pub fn new() -> Self {
-        return Self {
+    /// Sizes outside of `MIN_SIZE..=MAX_SIZE` aren't supported.
+    pub fn new(size: u16) -> Self {
+        let square_count = (size * size * size * size) as usize;
+        Self {
             screen: Screen::new(false),
-            square_vals: [[[[' '; 3]; 3]; 3]; 3],
-            square_fgs: [[[[Color::White; 3]; 3]; 3]; 3],
-            square_bgs: [[[[Color::Black; 3]; 3]; 3]; 3],
-            outer_square_bgs: [[Color::Black; 3]; 3],
+            size,
+            square_vals: vec![' '; square_count],
+            square_fgs: vec![Color::White; square_count],
+            square_bgs: vec![Color::Black; square_count],
+            outer_square_bgs: vec![Color::Black; (size * size) as usize],
         }
     }
 
+    fn full_render_height(&self) -> u16 {
+        self.size * (inner_render_height(self.size) + 1)
+    }
+
     pub fn full_render(&self) {
         let terminal = terminal(&self.screen);
         let cursor = cursor(&self.screen);
@@ -133,28 +188,30 @@ impl UI {
         terminal.clear(ClearType::All);
         cursor.goto(0, 0);
 
-        style(INITIAL_RENDER).paint(&self.screen);
+        style(initial_render(self.size)).paint(&self.screen);
     }
 
     pub fn inner_square(&mut self,
                         value: char,
                         coords: (Coord, Coord),
                         style: Style) {
+        let index = index_4d(self.size, coords);
+
         // Figure out colors
-        let fg = style.fg_or(get_4d_arr(&mut self.square_fgs, coords));
+        let fg = style.fg_or(self.square_fgs[index]);
         let bg = if style.clear_bg {
-            get_2d_arr(&mut self.outer_square_bgs, coords.0)
+            self.outer_square_bgs[index_2d(self.size, coords.0)]
         } else {
-            style.bg_or(get_4d_arr(&mut self.square_bgs, coords))
+            style.bg_or(self.square_bgs[index])
         };
 
         // Save options
-        set_4d_arr(&mut self.square_vals, coords, value);
-        set_4d_arr(&mut self.square_fgs, coords, fg);
-        set_4d_arr(&mut self.square_bgs, coords, bg);
+        self.square_vals[index] = value;
+        self.square_fgs[index] = fg;
+        self.square_bgs[index] = bg;
 
         // Update ui
-        let pos = inner_square_coord_to_pos(coords);
+        let pos = inner_square_coord_to_pos(self.size, coords);
         cursor(&self.screen).goto(pos.0, pos.1);
         crossterm::style(value).with(fg).on(bg).paint(&self.screen);
         self.reset_cursor_position();
@@ -163,42 +220,61 @@ impl UI {
     pub fn color_inner_square(&mut self,
                         coords: (Coord, Coord),
                         style: Style) {
-        let value = get_4d_arr(&self.square_vals, coords);
+        let value = self.square_vals[index_4d(self.size, coords)];
         self.inner_square(value, coords, style);
     }
 
     pub fn outer_square_grid(&mut self, outer: Coord, style: Style) {
-        self.draw_outer_square(&INNER_GRID_RENDER, outer, style.clone());
+        let render = inner_grid_render(self.size);
+        self.draw_outer_square(&render, outer, style.clone());
 
         // Draw square contents
-        for ix in 0..3 {
-            for iy in 0..3 {
+        for ix in 0..self.size {
+            for iy in 0..self.size {
                 self.color_inner_square((outer, (ix, iy)), style.clone());
             }
         }
     }
 
     pub fn outer_square_x(&mut self, outer: Coord, style: Style) {
-        self.draw_outer_square(&INNER_X_RENDER, outer, style.clone());
+        let render = fit_render(&INNER_X_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
     }
 
     pub fn outer_square_o(&mut self, outer: Coord, style: Style) {
-        self.draw_outer_square(&INNER_O_RENDER, outer, style.clone());
+        let render = fit_render(&INNER_O_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
+    }
+
+    pub fn outer_square_triangle(&mut self, outer: Coord, style: Style) {
+        let render = fit_render(&INNER_TRIANGLE_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
+    }
+
+    pub fn outer_square_box(&mut self, outer: Coord, style: Style) {
+        let render = fit_render(&INNER_BOX_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
     }
 
     pub fn outer_square_draw(&mut self, outer: Coord, style: Style) {
-        self.draw_outer_square(&INNER_DRAW_RENDER, outer, style.clone());
+        let render = fit_render(&INNER_DRAW_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
+    }
+
+    pub fn outer_square_wild(&mut self, outer: Coord, style: Style) {
+        let render = fit_render(&INNER_WILD_RENDER, self.size);
+        self.draw_outer_square(&render, outer, style.clone());
     }
 
-    fn draw_outer_square(&mut self, render: &InnerRender, outer: Coord, style: Style) {
+    fn draw_outer_square(&mut self, render: &[String], outer: Coord, style: Style) {
         // Figure out colors
         let fg = style.fg_or(Color::White);
         let bg = style.bg_or(Color::Black);
 
-        set_2d_arr(&mut self.outer_square_bgs, outer, bg);
+        self.outer_square_bgs[index_2d(self.size, outer)] = bg;
 
         let cursor = cursor(&self.screen);
-        let (initial_x, initial_y) = outer_square_coord_to_pos(outer);
+        let (initial_x, initial_y) = outer_square_coord_to_pos(self.size, outer);
 
         for (i, line) in render.iter().enumerate() {
             cursor.goto(initial_x, initial_y + i as u16);
@@ -207,24 +283,22 @@ impl UI {
     }
 
     pub fn read_input(&self) -> Input {
-        let input = input(&self.screen);
+        self.reset_cursor_position();
+        read_input(&self.screen)
+    }
 
+    /// Shows a line of text below the board, replacing the previous one.
+    pub fn message(&self, text: &str) {
+        let terminal = terminal(&self.screen);
+        cursor(&self.screen).goto(0, self.full_render_height());
+        terminal.clear(ClearType::CurrentLine);
+        style(text).paint(&self.screen);
         self.reset_cursor_position();
-        match input.read_char() {
-            Ok('x') | Ok('q') => Input::Exit,
-            Ok('w') | Ok('k') => Input::Move(Direction::Up),
-            Ok('s') | Ok('j') => Input::Move(Direction::Down),
-            Ok('a') | Ok('h') => Input::Move(Direction::Left),
-            Ok('d') | Ok('l') => Input::Move(Direction::Right),
-            Ok(' ') => Input::Select,
-            Ok(_) => Input::Invalid,
-            Err(err) => Input::Error(err),
-        }
     }
 
     pub fn reset_cursor_position(&self) {
         let cursor = cursor(&self.screen);
-        cursor.goto(0, FULL_RENDER_HEIGHT);
+        cursor.goto(0, self.full_render_height());
     }
 }
 
@@ -232,32 +306,117 @@ impl Drop for UI {
     fn drop(&mut self) {
         let cursor = cursor(&self.screen);
 
-        cursor.goto(0, FULL_RENDER_HEIGHT);
+        cursor.goto(0, self.full_render_height());
         cursor.show();
     }
 }
 
-fn get_2d_arr<T: Copy>(arr: &[[T; 3]; 3], (x, y): Coord) -> T {
-    arr[x as usize][y as usize]
+fn read_input(screen: &Screen) -> Input {
+    match input(screen).read_char() {
+        Ok('x') | Ok('q') => Input::Exit,
+        Ok('w') | Ok('k') => Input::Move(Direction::Up),
+        Ok('s') | Ok('j') => Input::Move(Direction::Down),
+        Ok('a') | Ok('h') => Input::Move(Direction::Left),
+        Ok('d') | Ok('l') => Input::Move(Direction::Right),
+        Ok(' ') => Input::Select,
+        Ok('p') => Input::Swap,
+        Ok('r') => Input::Resign,
+        Ok('o') => Input::Draw,
+        Ok(_) => Input::Invalid,
+        Err(err) => Input::Error(err),
+    }
 }
 
-fn set_2d_arr<T>(arr: &mut [[T; 3]; 3], (x, y): Coord, val: T) {
-    arr[x as usize][y as usize] = val;
+/// The widest grid of squares `CompactUI` draws, which fits a terminal
+/// 80 characters wide.
+pub const MAX_COMPACT_WIDTH: usize = 27;
+
+/// Draws games nested deeper than the outer board, one character per square.
+/// Boards are set apart by a gap for every level they're below the top.
+pub struct CompactUI {
+    screen: Screen,
+    size: u16,
+    depth: u16,
 }
 
-fn get_4d_arr<T: Copy>(arr: &[[[[T; 3]; 3]; 3]; 3], ((ox, oy), (ix, iy)): (Coord, Coord)) -> T {
-    arr[ox as usize][oy as usize][ix as usize][iy as usize]
+impl CompactUI {
+    /// Grids wider than `MAX_COMPACT_WIDTH` squares aren't supported.
+    pub fn new(size: u16, depth: u16) -> Self {
+        Self {
+            screen: Screen::new(false),
+            size,
+            depth,
+        }
+    }
+
+    // The number of gaps before the given column or row of squares
+    fn gaps_before(&self, i: u16) -> u16 {
+        (1..self.depth).map(|level| i / self.size.pow(level as u32)).sum()
+    }
+
+    fn full_render_height(&self) -> u16 {
+        let width = self.size.pow(self.depth as u32);
+        OUTER_GRID_OFFSET.1 + width + self.gaps_before(width - 1) + 1
+    }
+
+    pub fn full_render(&self) {
+        let terminal = terminal(&self.screen);
+        let cursor = cursor(&self.screen);
+
+        cursor.hide();
+        terminal.clear(ClearType::All);
+    }
+
+    /// Draws the square at `(col, row)` of the grid of all squares.
+    pub fn square(&self, value: char, (col, row): Coord, style: Style) {
+        let x = OUTER_GRID_OFFSET.0 + (col + self.gaps_before(col)) * 2;
+        let y = OUTER_GRID_OFFSET.1 + row + self.gaps_before(row);
+
+        cursor(&self.screen).goto(x, y);
+        crossterm::style(value).with(style.fg_or(Color::White)).on(style.bg_or(Color::Black)).paint(&self.screen);
+        self.reset_cursor_position();
+    }
+
+    pub fn read_input(&self) -> Input {
+        self.reset_cursor_position();
+        read_input(&self.screen)
+    }
+
+    /// Shows a line of text below the board, replacing the previous one.
+    pub fn message(&self, text: &str) {
+        let terminal = terminal(&self.screen);
+        cursor(&self.screen).goto(0, self.full_render_height());
+        terminal.clear(ClearType::CurrentLine);
+        style(text).paint(&self.screen);
+        self.reset_cursor_position();
+    }
+
+    pub fn reset_cursor_position(&self) {
+        cursor(&self.screen).goto(0, self.full_render_height());
+    }
 }
 
-fn set_4d_arr<T>(arr: &mut [[[[T; 3]; 3]; 3]; 3], ((ox, oy), (ix, iy)): (Coord, Coord), val: T) {
-    arr[ox as usize][oy as usize][ix as usize][iy as usize] = val;
+impl Drop for CompactUI {
+    fn drop(&mut self) {
+        let cursor = cursor(&self.screen);
+
+        cursor.goto(0, self.full_render_height());
+        cursor.show();
+    }
+}
+
+fn index_2d(size: u16, (x, y): Coord) -> usize {
+    (x * size + y) as usize
+}
+
+fn index_4d(size: u16, (outer, inner): (Coord, Coord)) -> usize {
+    index_2d(size, outer) * (size * size) as usize + index_2d(size, inner)
 }
 
 #[derive(Clone)]
 pub struct Style {
     fg: Option<Color>,
     bg: Option<Color>,
-    clear_fg: bool,
     clear_bg: bool,
 }
 
@@ -266,7 +425,6 @@ impl Style {
         Self {
             fg: None,
             bg: None,
-            clear_fg: false,
             clear_bg: false,
         }
     }
@@ -278,10 +436,6 @@ impl Style {
     pub fn fg_or(&self, default: Color) -> Color {
         self.fg.unwrap_or(default)
     }
-    pub fn clear_fg(mut self) -> Self {
-        self.clear_fg = true;
-        self
-    }
 
     pub fn bg(mut self, color: Color) -> Self {
         self.bg = Some(color);
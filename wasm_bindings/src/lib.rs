@@ -6,6 +6,7 @@ extern crate cfg_if;
 extern crate serde_derive;
 
 use ultimate_tic_tac_toe::*;
+use ultimate_tic_tac_toe::nested::{NestedCoord, NestedGame};
 use wasm_bindgen::prelude::*;
 
 use cfg_if::cfg_if;
@@ -24,6 +25,8 @@ cfg_if! {
 pub enum JsPiece {
     #[serde(rename = "x")] X,
     #[serde(rename = "o")] O,
+    #[serde(rename = "triangle")] Triangle,
+    #[serde(rename = "box")] Box,
 }
 
 impl From<Piece> for JsPiece {
@@ -31,44 +34,331 @@ impl From<Piece> for JsPiece {
         match piece {
             Piece::X => JsPiece::X,
             Piece::O => JsPiece::O,
+            Piece::Triangle => JsPiece::Triangle,
+            Piece::Box => JsPiece::Box,
         }
     }
 }
 
+impl From<JsPiece> for Piece {
+    fn from(piece: JsPiece) -> Self {
+        match piece {
+            JsPiece::X => Piece::X,
+            JsPiece::O => Piece::O,
+            JsPiece::Triangle => Piece::Triangle,
+            JsPiece::Box => Piece::Box,
+        }
+    }
+}
+
+// The piece at the start of states like `x-wins`, named as in `JsPiece`
+fn piece_name(piece: Piece) -> &'static str {
+    match piece {
+        Piece::X => "x",
+        Piece::O => "o",
+        Piece::Triangle => "triangle",
+        Piece::Box => "box",
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub enum JsCompleteState {
     #[serde(rename = "x")] X,
     #[serde(rename = "o")] O,
+    #[serde(rename = "triangle")] Triangle,
+    #[serde(rename = "box")] Box,
     #[serde(rename = "draw")] Draw,
+    // A draw that counts for both players on the outer board
+    #[serde(rename = "wild")] Wild,
 }
 
-impl From<GameOverResult> for JsCompleteState {
-    fn from(result: GameOverResult) -> Self {
+impl JsCompleteState {
+    fn new(result: GameOverResult, rules: &RuleSet) -> Self {
         match result {
-            GameOverResult::Draw => JsCompleteState::Draw,
+            GameOverResult::Draw => match rules.drawn_boards {
+                DrawnBoardRule::CountsForNobody => JsCompleteState::Draw,
+                DrawnBoardRule::CountsForBoth => JsCompleteState::Wild,
+            },
             GameOverResult::Winner(Piece::X, _) => JsCompleteState::X,
             GameOverResult::Winner(Piece::O, _) => JsCompleteState::O,
+            GameOverResult::Winner(Piece::Triangle, _) => JsCompleteState::Triangle,
+            GameOverResult::Winner(Piece::Box, _) => JsCompleteState::Box,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum JsCompletedBoardRule {
+    #[serde(rename = "free-move")] FreeMove,
+    #[serde(rename = "play-inside")] PlayInside,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum JsDrawnBoardRule {
+    #[serde(rename = "nobody")] CountsForNobody,
+    #[serde(rename = "both")] CountsForBoth,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum JsOuterLineRule {
+    #[serde(rename = "first-line")] FirstLineWins,
+    #[serde(rename = "most-lines")] MostLinesWin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum JsTiebreak {
+    #[default] #[serde(rename = "none")] None,
+    #[serde(rename = "most-boards")] MostBoards,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum JsMisere {
+    #[default] #[serde(rename = "off")] Off,
+    #[serde(rename = "outer")] OuterBoard,
+    #[serde(rename = "all")] AllBoards,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum JsPieRule {
+    #[default] #[serde(rename = "off")] Off,
+    #[serde(rename = "swap")] SwapAfterFirstMove,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum JsDeadDrawRule {
+    #[default] #[serde(rename = "play-out")] PlayOut,
+    #[serde(rename = "end-early")] EndEarly,
+}
+
+fn default_size() -> usize {
+    SIZE
+}
+
+fn default_players() -> usize {
+    2
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct JsRuleSet {
+    #[serde(default = "default_size")]
+    pub size: usize,
+    #[serde(rename = "winLength", default = "default_size")]
+    pub win_length: usize,
+    #[serde(default = "default_players")]
+    pub players: usize,
+    #[serde(rename = "completedBoards")]
+    pub completed_boards: JsCompletedBoardRule,
+    #[serde(rename = "drawnBoards")]
+    pub drawn_boards: JsDrawnBoardRule,
+    #[serde(rename = "outerLines")]
+    pub outer_lines: JsOuterLineRule,
+    #[serde(default)]
+    pub tiebreak: JsTiebreak,
+    #[serde(default)]
+    pub misere: JsMisere,
+    #[serde(rename = "pieRule", default)]
+    pub pie_rule: JsPieRule,
+    #[serde(rename = "deadDraws", default)]
+    pub dead_draws: JsDeadDrawRule,
+}
+
+impl Default for JsRuleSet {
+    fn default() -> Self {
+        RuleSet::default().into()
+    }
+}
+
+impl From<RuleSet> for JsRuleSet {
+    fn from(rules: RuleSet) -> Self {
+        JsRuleSet {
+            size: rules.size,
+            win_length: rules.win_length,
+            players: rules.players,
+            completed_boards: match rules.completed_boards {
+                CompletedBoardRule::FreeMove => JsCompletedBoardRule::FreeMove,
+                CompletedBoardRule::PlayInside => JsCompletedBoardRule::PlayInside,
+            },
+            drawn_boards: match rules.drawn_boards {
+                DrawnBoardRule::CountsForNobody => JsDrawnBoardRule::CountsForNobody,
+                DrawnBoardRule::CountsForBoth => JsDrawnBoardRule::CountsForBoth,
+            },
+            outer_lines: match rules.outer_lines {
+                OuterLineRule::FirstLineWins => JsOuterLineRule::FirstLineWins,
+                OuterLineRule::MostLinesWin => JsOuterLineRule::MostLinesWin,
+            },
+            tiebreak: match rules.tiebreak {
+                Tiebreak::None => JsTiebreak::None,
+                Tiebreak::MostBoards => JsTiebreak::MostBoards,
+            },
+            misere: match rules.misere {
+                Misere::Off => JsMisere::Off,
+                Misere::OuterBoard => JsMisere::OuterBoard,
+                Misere::AllBoards => JsMisere::AllBoards,
+            },
+            pie_rule: match rules.pie_rule {
+                PieRule::Off => JsPieRule::Off,
+                PieRule::SwapAfterFirstMove => JsPieRule::SwapAfterFirstMove,
+            },
+            dead_draws: match rules.dead_draws {
+                DeadDrawRule::PlayOut => JsDeadDrawRule::PlayOut,
+                DeadDrawRule::EndEarly => JsDeadDrawRule::EndEarly,
+            },
+        }
+    }
+}
+
+impl From<JsRuleSet> for RuleSet {
+    fn from(rules: JsRuleSet) -> Self {
+        RuleSet {
+            size: rules.size,
+            win_length: rules.win_length,
+            players: rules.players,
+            completed_boards: match rules.completed_boards {
+                JsCompletedBoardRule::FreeMove => CompletedBoardRule::FreeMove,
+                JsCompletedBoardRule::PlayInside => CompletedBoardRule::PlayInside,
+            },
+            drawn_boards: match rules.drawn_boards {
+                JsDrawnBoardRule::CountsForNobody => DrawnBoardRule::CountsForNobody,
+                JsDrawnBoardRule::CountsForBoth => DrawnBoardRule::CountsForBoth,
+            },
+            outer_lines: match rules.outer_lines {
+                JsOuterLineRule::FirstLineWins => OuterLineRule::FirstLineWins,
+                JsOuterLineRule::MostLinesWin => OuterLineRule::MostLinesWin,
+            },
+            tiebreak: match rules.tiebreak {
+                JsTiebreak::None => Tiebreak::None,
+                JsTiebreak::MostBoards => Tiebreak::MostBoards,
+            },
+            misere: match rules.misere {
+                JsMisere::Off => Misere::Off,
+                JsMisere::OuterBoard => Misere::OuterBoard,
+                JsMisere::AllBoards => Misere::AllBoards,
+            },
+            pie_rule: match rules.pie_rule {
+                JsPieRule::Off => PieRule::Off,
+                JsPieRule::SwapAfterFirstMove => PieRule::SwapAfterFirstMove,
+            },
+            dead_draws: match rules.dead_draws {
+                JsDeadDrawRule::PlayOut => DeadDrawRule::PlayOut,
+                JsDeadDrawRule::EndEarly => DeadDrawRule::EndEarly,
+            },
         }
     }
 }
 
+// Lines are sent as every square they consist of, see `Line::coords`.
+type JsLine = Vec<(usize, usize)>;
+
+fn line_to_js(line: Line) -> JsLine {
+    line.coords().map(|Coord { x, y }| (x, y)).collect()
+}
+
+fn line_from_js(line: &JsLine) -> Result<Line, String> {
+    let coord = |(x, y): (usize, usize)| Coord { x, y };
+    match (line.first(), line.last()) {
+        (Some(&first), Some(&last)) => Line::between(coord(first), coord(last))
+            .ok_or_else(|| format!("Not a line: {:?}", line)),
+        _ => Err("Empty line".into()),
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum JsOrientation {
+    #[serde(rename = "column")] Column,
+    #[serde(rename = "row")] Row,
+    #[serde(rename = "diagonal")] Diagonal,
+    #[serde(rename = "anti-diagonal")] AntiDiagonal,
+}
+
+impl From<Orientation> for JsOrientation {
+    fn from(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Column => JsOrientation::Column,
+            Orientation::Row => JsOrientation::Row,
+            Orientation::Diagonal => JsOrientation::Diagonal,
+            Orientation::AntiDiagonal => JsOrientation::AntiDiagonal,
+        }
+    }
+}
+
+/// One of the lines completed by the winning move, for drawing a strike through it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsWinningLine {
+    /// Only sent to JS, the squares are enough to read the line back.
+    pub orientation: JsOrientation,
+    pub squares: JsLine,
+}
+
+// The first line, as sent before all completed lines were, and every line
+fn lines_to_js(lines: Lines) -> (Option<JsLine>, Vec<JsWinningLine>) {
+    let winning_lines = lines.iter()
+        .map(|line| JsWinningLine { orientation: line.orientation().into(), squares: line_to_js(line) })
+        .collect();
+    (lines.first().map(line_to_js), winning_lines)
+}
+
+// Prefers every line, falling back to the first one for games from older versions
+fn lines_from_js(winning_triple: &Option<JsLine>, winning_lines: &[JsWinningLine]) -> Result<Lines, String> {
+    if winning_lines.is_empty() {
+        let line = winning_triple.as_ref().ok_or("A won board needs its winning line")?;
+        line_from_js(line).map(Lines::from)
+    } else {
+        winning_lines.iter().map(|line| line_from_js(&line.squares)).collect()
+    }
+}
+
+fn winning_lines(result: GameOverResult) -> (Option<JsLine>, Vec<JsWinningLine>) {
+    match result {
+        GameOverResult::Winner(_, WinReason::Line(lines)) | GameOverResult::Winner(_, WinReason::MisereLine(lines)) =>
+            lines_to_js(lines),
+        _ => (None, vec![]),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct JsInnerGrid {
-    pub grid: [[Option<JsPiece>; SIZE]; SIZE],
+    pub grid: Vec<Vec<Option<JsPiece>>>,
     pub completed: Option<JsCompleteState>,
     #[serde(rename = "winningTriple")]
-    pub winning_triple: Option<((usize, usize), (usize, usize), (usize, usize))>,
+    pub winning_triple: Option<JsLine>,
+    #[serde(rename = "winningLines", default)]
+    pub winning_lines: Vec<JsWinningLine>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct JsGame {
-    pub grid: [[JsInnerGrid; SIZE]; SIZE],
+    #[serde(default)]
+    pub rules: JsRuleSet,
+    pub grid: Vec<Vec<JsInnerGrid>>,
     pub turn: JsPiece,
     pub state: String,
     #[serde(rename = "activeOuterSquare")]
     pub active_outer_square: Option<JsCoord>,
+    /// The first line completed by the winning move.
     #[serde(rename = "winningTriple")]
-    pub winning_triple: Option<((usize, usize), (usize, usize), (usize, usize))>,
+    pub winning_triple: Option<JsLine>,
+    /// Every line completed by the winning move.
+    #[serde(rename = "winningLines", default)]
+    pub winning_lines: Vec<JsWinningLine>,
+    /// The winner's and the opponent's count when the game was won on lines or boards.
+    #[serde(rename = "winCounts", default)]
+    pub win_counts: Option<(usize, usize)>,
+    /// The number of moves played, which decides when the players may swap
+    /// sides. Games saved without it count every piece on the board.
+    #[serde(rename = "movesPlayed", default)]
+    pub moves_played: Option<usize>,
+    /// Whether the players swapped sides under the pie rule.
+    #[serde(default)]
+    pub swapped: bool,
+    /// Only sent to JS, recomputed from the game when read back.
+    #[serde(rename = "canSwap", default)]
+    pub can_swap: bool,
+    /// Whether the game can only end in a draw. Only sent to JS, like `canSwap`.
+    #[serde(rename = "deadDraw", default)]
+    pub dead_draw: bool,
+    /// The player whose draw offer is waiting for an answer.
+    #[serde(rename = "drawOffer", default)]
+    pub draw_offer: Option<JsPiece>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -86,18 +376,58 @@ pub struct JsMove {
     turn: JsPiece,
 }
 
+/// Where a move sends the opponent and what it wins, see `Game::move_consequence`.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct JsMoveConsequence {
+    #[serde(rename = "move")]
+    move_: JsMove,
+    destination: Option<JsCoord>,
+    #[serde(rename = "freeMove")]
+    free_move: bool,
+    #[serde(rename = "winsInnerBoard")]
+    wins_inner_board: bool,
+    #[serde(rename = "winsGame")]
+    wins_game: bool,
+}
+
+// Describes how a game stands, as `in-progress`, `draw` or `x-wins` and the like
+fn state_string(state: GameState) -> String {
+    use GameState::*;
+    use GameOverResult::*;
+    match state {
+        InProgress => "in-progress".into(),
+        GameOver(Draw) => "draw".into(),
+        AgreedDraw => "draw-agreed".into(),
+        GameOver(Winner(piece, reason)) => {
+            let reason = match reason {
+                WinReason::Line(_) => "",
+                WinReason::MisereLine(_) => "-by-misere",
+                WinReason::MostLines(_, _) => "-by-lines",
+                WinReason::MostBoards(_, _) => "-by-count",
+                WinReason::Resignation => "-by-resignation",
+                WinReason::Forfeit(ForfeitReason::Timeout) => "-by-timeout",
+                WinReason::Forfeit(ForfeitReason::IllegalMove) => "-by-illegal-move",
+                WinReason::Forfeit(ForfeitReason::Disconnection) => "-by-disconnection",
+            };
+            format!("{}-wins{}", piece_name(piece), reason)
+        },
+    }
+}
+
 fn game_to_jsgame(game: &Game) -> JsValue {
-    let mut grid = [[JsInnerGrid {
-        grid: [[None; SIZE]; SIZE],
+    let size = game.size();
+    let mut grid = vec![vec![JsInnerGrid {
+        grid: vec![vec![None; size]; size],
         completed: None,
         winning_triple: None,
-    }; SIZE]; SIZE];
-
-    for ox in 0..SIZE {
-        for oy in 0..SIZE {
-            for ix in 0..SIZE {
-                for iy in 0..SIZE {
-                    let coord = FullCoord::try_new((ox, oy), (ix, iy)).unwrap();
+        winning_lines: vec![],
+    }; size]; size];
+
+    for ox in 0..size {
+        for oy in 0..size {
+            for ix in 0..size {
+                for iy in 0..size {
+                    let coord = FullCoord::try_new_sized((ox, oy), (ix, iy), size).unwrap();
                     grid[ox][oy].grid[ix][iy] = match game.get_square(&coord) {
                         Square::Empty => None,
                         Square::Filled(piece) => Some(piece.into()),
@@ -105,30 +435,32 @@ fn game_to_jsgame(game: &Game) -> JsValue {
                 }
             }
 
-            let coord = Coord::try_new(ox, oy).unwrap();
+            let coord = Coord::try_new_sized(ox, oy, size).unwrap();
 
             grid[ox][oy].completed = match game.get_outer_square(&coord) {
                 OuterSquare::Incomplete => None,
-                OuterSquare::Complete(result) => Some(result.into()),
-            };
-            grid[ox][oy].winning_triple = match game.get_outer_square(&coord) {
-                OuterSquare::Complete(GameOverResult::Winner(_, triple)) => Some(triple),
-                _ => None,
+                OuterSquare::Complete(result) => Some(JsCompleteState::new(result, &game.rules())),
             };
+            if let OuterSquare::Complete(result) = game.get_outer_square(&coord) {
+                let (winning_triple, winning_lines) = winning_lines(result);
+                grid[ox][oy].winning_triple = winning_triple;
+                grid[ox][oy].winning_lines = winning_lines;
+            }
         }
     }
 
     use GameState::*;
     use GameOverResult::*;
-    let state = match game.state() {
-        InProgress => "in-progress",
-        GameOver(Draw) => "draw",
-        GameOver(Winner(Piece::X, _)) => "x-wins",
-        GameOver(Winner(Piece::O, _)) => "o-wins",
-    }.into();
-
-    let winning_triple = match game.state() {
-        GameOver(Winner(_, triple)) => Some(triple),
+    let state = state_string(game.state());
+
+    let (winning_triple, winning_lines) = match game.state() {
+        GameOver(result) => winning_lines(result),
+        InProgress | AgreedDraw => (None, vec![]),
+    };
+
+    let win_counts = match game.state() {
+        GameOver(Winner(_, WinReason::MostLines(won, lost))) => Some((won, lost)),
+        GameOver(Winner(_, WinReason::MostBoards(won, lost))) => Some((won, lost)),
         _ => None,
     };
 
@@ -137,71 +469,118 @@ fn game_to_jsgame(game: &Game) -> JsValue {
         .map(|Coord { x, y }| JsCoord { x: x as u8, y: y as u8 });
 
     let game = JsGame {
+        rules: game.rules().into(),
         grid,
         turn: game.turn().into(),
         state,
         active_outer_square,
         winning_triple,
+        winning_lines,
+        win_counts,
+        moves_played: Some(game.moves_played()),
+        swapped: game.swapped(),
+        can_swap: game.can_swap(),
+        dead_draw: game.is_dead_draw(),
+        draw_offer: game.draw_offer().map(JsPiece::from),
     };
 
     JsValue::from_serde(&game).unwrap()
 }
 
-fn jsgame_to_game(js_game: &JsGame) -> Game {
+fn jsgame_to_game(js_game: &JsGame) -> Result<Game, String> {
     use GameState::*;
     use GameOverResult::*;
 
+    let winning_line = || lines_from_js(&js_game.winning_triple, &js_game.winning_lines);
+    let winner = |state: &str| Piece::ALL.iter().cloned()
+        .find(|&piece| state.starts_with(&format!("{}-wins", piece_name(piece))));
     let state = match &js_game.state[..] {
         "in-progress" => InProgress,
         "draw" => GameOver(Draw),
-        "x-wins" => GameOver(Winner(Piece::X, js_game.winning_triple.unwrap())),
-        "o-wins" => GameOver(Winner(Piece::O, js_game.winning_triple.unwrap())),
-        _ => InProgress,
+        "draw-agreed" => AgreedDraw,
+        state => match winner(state) {
+            Some(piece) => {
+                let win_counts = || js_game.win_counts.ok_or_else(|| format!("Missing winCounts for {:?}", state));
+                let reason = match &state[piece_name(piece).len() + "-wins".len()..] {
+                    "" => WinReason::Line(winning_line()?),
+                    "-by-misere" => WinReason::MisereLine(winning_line()?),
+                    "-by-lines" => win_counts().map(|(won, lost)| WinReason::MostLines(won, lost))?,
+                    "-by-count" => win_counts().map(|(won, lost)| WinReason::MostBoards(won, lost))?,
+                    "-by-resignation" => WinReason::Resignation,
+                    "-by-timeout" => WinReason::Forfeit(ForfeitReason::Timeout),
+                    "-by-illegal-move" => WinReason::Forfeit(ForfeitReason::IllegalMove),
+                    "-by-disconnection" => WinReason::Forfeit(ForfeitReason::Disconnection),
+                    _ => return Err(format!("Unknown game state: {:?}", state)),
+                };
+                GameOver(Winner(piece, reason))
+            },
+            None => return Err(format!("Unknown game state: {:?}", state)),
+        },
     };
 
-    let turn = match js_game.turn {
-        JsPiece::X => Piece::X,
-        JsPiece::O => Piece::O,
-    };
+    let turn = js_game.turn.into();
+
+    let rules: RuleSet = js_game.rules.into();
+    let size = rules.size;
 
     let active_outer_square = js_game.active_outer_square
-        .map(|JsCoord { x, y }| Coord::try_new(x as usize, y as usize).unwrap());
+        .map(|JsCoord { x, y }| Coord::try_new_sized(x as usize, y as usize, size).unwrap());
+
+    // Inner boards are only ever won by lines, which lose under misère rules
+    let inner_win = |inner_grid: &JsInnerGrid| -> Result<WinReason, String> {
+        let lines = lines_from_js(&inner_grid.winning_triple, &inner_grid.winning_lines)?;
+        Ok(match rules.misere {
+            Misere::AllBoards => WinReason::MisereLine(lines),
+            _ => WinReason::Line(lines),
+        })
+    };
 
-    let mut board = [[[[Square::Empty; SIZE]; SIZE]; SIZE]; SIZE];
-    let mut outer_board = [[OuterSquare::Incomplete; SIZE]; SIZE];
+    // Pushed in the order of significance documented on `Game::board`
+    let mut board = Vec::with_capacity(size * size * size * size);
+    let mut outer_board = Vec::with_capacity(size * size);
 
-    for ox in 0..SIZE {
-        for oy in 0..SIZE {
-            let inner_grid = js_game.grid[ox][oy];
+    for ox in 0..size {
+        for oy in 0..size {
+            let inner_grid = &js_game.grid[ox][oy];
 
-            outer_board[ox][oy] = match inner_grid.completed {
+            outer_board.push(match inner_grid.completed {
                 None => OuterSquare::Incomplete,
-                Some(JsCompleteState::Draw) => OuterSquare::Complete(Draw),
+                Some(JsCompleteState::Draw) | Some(JsCompleteState::Wild) => OuterSquare::Complete(Draw),
                 Some(JsCompleteState::X) => OuterSquare::Complete(
-                    Winner(Piece::X, inner_grid.winning_triple.unwrap())),
+                    Winner(Piece::X, inner_win(inner_grid)?)),
                 Some(JsCompleteState::O) => OuterSquare::Complete(
-                    Winner(Piece::O, inner_grid.winning_triple.unwrap())),
-            };
-
-            for ix in 0..SIZE {
-                for iy in 0..SIZE {
-                    board[ox][oy][ix][iy] = match inner_grid.grid[ix][iy] {
+                    Winner(Piece::O, inner_win(inner_grid)?)),
+                Some(JsCompleteState::Triangle) => OuterSquare::Complete(
+                    Winner(Piece::Triangle, inner_win(inner_grid)?)),
+                Some(JsCompleteState::Box) => OuterSquare::Complete(
+                    Winner(Piece::Box, inner_win(inner_grid)?)),
+            });
+
+            for ix in 0..size {
+                for iy in 0..size {
+                    board.push(match inner_grid.grid[ix][iy] {
                         None => Square::Empty,
-                        Some(JsPiece::X) => Square::Filled(Piece::X),
-                        Some(JsPiece::O) => Square::Filled(Piece::O),
-                    }
+                        Some(piece) => Square::Filled(piece.into()),
+                    });
                 }
             }
         }
     }
 
-    Game {
+    let moves_played = js_game.moves_played
+        .unwrap_or_else(|| board.iter().filter(|&&square| square != Square::Empty).count());
+
+    Ok(Game {
+        rules,
         state,
         turn,
         board,
         outer_board,
         active_outer_square,
-    }
+        moves_played,
+        swapped: js_game.swapped,
+        draw_offer: js_game.draw_offer.map(Piece::from),
+    })
 }
 
 #[wasm_bindgen]
@@ -211,10 +590,40 @@ pub fn initialize() -> JsValue {
     game_to_jsgame(&Game::new())
 }
 
+#[wasm_bindgen(js_name = initializeWithRules)]
+pub fn initialize_with_rules(rules: JsValue) -> Result<JsValue, JsValue> {
+    set_panic_hook();
+
+    let js_rules: JsRuleSet = rules.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let rules: RuleSet = js_rules.into();
+    rules.validate().map_err(|err| JsValue::from_str(&err))?;
+
+    Ok(game_to_jsgame(&Game::new_with_rules(rules)))
+}
+
+/// Starts from a position written by `positionString`, see `Game::from_position`.
+#[wasm_bindgen(js_name = initializeFromPosition)]
+pub fn initialize_from_position(rules: JsValue, position: &str) -> Result<JsValue, JsValue> {
+    set_panic_hook();
+
+    let js_rules: JsRuleSet = rules.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let game = Game::from_position_with_rules(js_rules.into(), position)
+        .map_err(|err| JsValue::from_str(&err))?;
+
+    Ok(game_to_jsgame(&game))
+}
+
+/// Describes the position of a game as text, to save it or start from it later.
+#[wasm_bindgen(js_name = positionString)]
+pub fn position_string(game: JsValue) -> Result<String, JsValue> {
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?.position_string())
+}
+
 #[wasm_bindgen(js_name = getMoves)]
 pub fn get_moves(game: JsValue) -> Result<JsValue, JsValue> {
     let js_game: JsGame = game.into_serde().unwrap();
-    let moves = jsgame_to_game(&js_game).get_moves();
+    let moves = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?.get_moves();
 
     let mut js_moves: Vec<JsMove> = Vec::with_capacity(moves.len());
 
@@ -231,18 +640,237 @@ pub fn get_moves(game: JsValue) -> Result<JsValue, JsValue> {
     Ok(JsValue::from_serde(&js_moves).unwrap())
 }
 
+/// Why `move_` can't be played, or `null` if it can, see `Game::move_legality`.
+#[wasm_bindgen(js_name = moveLegality)]
+pub fn move_legality(game: JsValue, move_: JsValue) -> Result<JsValue, JsValue> {
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let js_move: JsMove = move_.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let game = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+    let coord = FullCoord::try_new_sized(
+        (js_move.ox as usize, js_move.oy as usize),
+        (js_move.ix as usize, js_move.iy as usize),
+        game.size(),
+    ).map_err(|err| JsValue::from_str(&err))?;
+
+    let reason = game.move_legality(&coord).err().map(|reason| reason.to_string());
+    Ok(JsValue::from_serde(&reason).unwrap())
+}
+
+/// The outer squares the side to move may play in.
+#[wasm_bindgen(js_name = playableOuterSquares)]
+pub fn playable_outer_squares(game: JsValue) -> Result<JsValue, JsValue> {
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let squares: Vec<_> = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?.playable_outer_squares().into_iter()
+        .map(|Coord { x, y }| JsCoord { x: x as u8, y: y as u8 })
+        .collect();
+
+    Ok(JsValue::from_serde(&squares).unwrap())
+}
+
+/// The consequences of every legal move, in the order of `getMoves`.
+#[wasm_bindgen(js_name = moveConsequences)]
+pub fn move_consequences(game: JsValue) -> Result<JsValue, JsValue> {
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let consequences: Vec<_> = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?.move_consequences().into_iter()
+        .map(|consequence| JsMoveConsequence {
+            move_: JsMove {
+                ix: consequence.full_coord.inner_x() as u8,
+                iy: consequence.full_coord.inner_y() as u8,
+                ox: consequence.full_coord.outer_x() as u8,
+                oy: consequence.full_coord.outer_y() as u8,
+                turn: js_game.turn,
+            },
+            destination: consequence.destination.map(|Coord { x, y }| JsCoord { x: x as u8, y: y as u8 }),
+            free_move: consequence.gives_free_move(),
+            wins_inner_board: consequence.wins_inner_board(),
+            wins_game: consequence.wins_game(),
+        })
+        .collect();
+
+    Ok(JsValue::from_serde(&consequences).unwrap())
+}
+
 #[wasm_bindgen(js_name = playMove)]
 pub fn play_move(game: JsValue, move_: JsValue) -> Result<JsValue, JsValue> {
     let js_game: JsGame = game.into_serde().unwrap();
     let js_move: JsMove = move_.into_serde().unwrap();
 
-    let mut game = jsgame_to_game(&js_game);
-    let coord = FullCoord::try_new(
+    let mut game = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+    let coord = FullCoord::try_new_sized(
         (js_move.ox as usize, js_move.oy as usize),
         (js_move.ix as usize, js_move.iy as usize),
+        game.size(),
     ).unwrap();
 
     game.play_move(&coord).unwrap();
 
     Ok(game_to_jsgame(&game))
 }
+
+#[wasm_bindgen]
+pub fn swap(game: JsValue) -> Result<JsValue, JsValue> {
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let mut game = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+    game.swap().map_err(|err| JsValue::from_str(&err))?;
+
+    Ok(game_to_jsgame(&game))
+}
+
+// Applies something `piece` does besides moving, such as resigning, to a JS game
+fn player_action<F>(game: JsValue, piece: JsValue, action: F) -> Result<JsValue, JsValue>
+    where F: FnOnce(&mut Game, Piece) -> Result<(), String>
+{
+    let js_game: JsGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let js_piece: JsPiece = piece.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let mut game = jsgame_to_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+    action(&mut game, js_piece.into()).map_err(|err| JsValue::from_str(&err))?;
+
+    Ok(game_to_jsgame(&game))
+}
+
+#[wasm_bindgen]
+pub fn resign(game: JsValue, piece: JsValue) -> Result<JsValue, JsValue> {
+    player_action(game, piece, Game::resign)
+}
+
+#[wasm_bindgen(js_name = offerDraw)]
+pub fn offer_draw(game: JsValue, piece: JsValue) -> Result<JsValue, JsValue> {
+    player_action(game, piece, Game::offer_draw)
+}
+
+#[wasm_bindgen(js_name = acceptDraw)]
+pub fn accept_draw(game: JsValue, piece: JsValue) -> Result<JsValue, JsValue> {
+    player_action(game, piece, Game::accept_draw)
+}
+
+/// A completed board of a nested game, below the top board.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsNestedBoard {
+    path: Vec<JsCoord>,
+    completed: JsCompleteState,
+}
+
+/// A game with boards nested `depth` levels deep, see `NestedGame`.
+///
+/// Only `depth`, `rules` and `moves` are read back, the game is replayed
+/// from them. Everything else is only sent to JS.
+#[derive(Serialize, Deserialize)]
+pub struct JsNestedGame {
+    pub depth: usize,
+    #[serde(default)]
+    pub rules: JsRuleSet,
+    /// Every move so far, like `1,1:0,2:2,2`.
+    pub moves: Vec<String>,
+    /// The cells on the grid of all cells, indexed `[col][row]`.
+    #[serde(default)]
+    pub cells: Vec<Vec<Option<JsPiece>>>,
+    /// Every completed board, from the top level down.
+    #[serde(default)]
+    pub boards: Vec<JsNestedBoard>,
+    #[serde(default = "default_turn")]
+    pub turn: JsPiece,
+    #[serde(default)]
+    pub state: String,
+    /// The path of the board to play in, empty if any cell may be played.
+    #[serde(rename = "activeBoard", default)]
+    pub active_board: Vec<JsCoord>,
+}
+
+fn default_turn() -> JsPiece {
+    JsPiece::X
+}
+
+fn nested_game_to_js(game: &NestedGame, moves: Vec<String>) -> JsValue {
+    let size = game.rules().size;
+    let width = size.pow(game.depth() as u32);
+    let to_js = |path: &[Coord]| path.iter().map(|&Coord { x, y }| JsCoord { x: x as u8, y: y as u8 }).collect();
+
+    let mut cells = vec![vec![None; width]; width];
+    for (col, column) in cells.iter_mut().enumerate() {
+        for (row, cell) in column.iter_mut().enumerate() {
+            let coord = NestedCoord::try_from_global((col, row), game.depth(), size).unwrap();
+            if let Square::Filled(piece) = game.get_square(&coord) {
+                *cell = Some(piece.into());
+            }
+        }
+    }
+
+    // Boards are listed level by level, so the path of each level is extended by one coordinate
+    let mut boards = vec![];
+    let mut paths: Vec<Vec<Coord>> = vec![vec![]];
+    for _ in 1..game.depth() {
+        paths = paths.iter()
+            .flat_map(|path| (0..size * size).map(move |i| {
+                let mut path = path.clone();
+                path.push(Coord::try_new_sized(i / size, i % size, size).unwrap());
+                path
+            }))
+            .collect();
+        for path in &paths {
+            if let Some(OuterSquare::Complete(result)) = game.get_board(path) {
+                boards.push(JsNestedBoard { path: to_js(path), completed: JsCompleteState::new(result, &game.rules()) });
+            }
+        }
+    }
+
+    let js_game = JsNestedGame {
+        depth: game.depth(),
+        rules: game.rules().into(),
+        moves,
+        cells,
+        boards,
+        turn: game.turn().into(),
+        state: state_string(game.state()),
+        active_board: to_js(game.active_board()),
+    };
+
+    JsValue::from_serde(&js_game).unwrap()
+}
+
+fn js_to_nested_game(js_game: &JsNestedGame) -> Result<NestedGame, String> {
+    let rules: RuleSet = js_game.rules.into();
+    let mut game = NestedGame::try_new_with_rules(js_game.depth, rules)?;
+    for move_ in &js_game.moves {
+        game.play_move(&NestedCoord::parse_sized(move_, rules.size)?)?;
+    }
+    Ok(game)
+}
+
+/// Starts a game nested `depth` levels deep, see `NestedGame::try_new_with_rules`.
+#[wasm_bindgen(js_name = initializeNested)]
+pub fn initialize_nested(depth: usize, rules: JsValue) -> Result<JsValue, JsValue> {
+    set_panic_hook();
+
+    let js_rules: JsRuleSet = rules.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let game = NestedGame::try_new_with_rules(depth, js_rules.into())
+        .map_err(|err| JsValue::from_str(&err))?;
+
+    Ok(nested_game_to_js(&game, vec![]))
+}
+
+/// The legal moves of a nested game, like `1,1:0,2:2,2`.
+#[wasm_bindgen(js_name = nestedMoves)]
+pub fn nested_moves(game: JsValue) -> Result<JsValue, JsValue> {
+    let js_game: JsNestedGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let game = js_to_nested_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+    let moves: Vec<String> = game.get_moves().iter().map(NestedCoord::to_string).collect();
+
+    Ok(JsValue::from_serde(&moves).unwrap())
+}
+
+/// Plays a move like `1,1:0,2:2,2`, or fails with the reason it's illegal.
+#[wasm_bindgen(js_name = playNestedMove)]
+pub fn play_nested_move(game: JsValue, move_: &str) -> Result<JsValue, JsValue> {
+    let js_game: JsNestedGame = game.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let mut game = js_to_nested_game(&js_game).map_err(|err| JsValue::from_str(&err))?;
+
+    let coord = NestedCoord::parse_sized(move_, game.rules().size).map_err(|err| JsValue::from_str(&err))?;
+    game.play_move(&coord).map_err(|err| JsValue::from_str(&err))?;
+
+    let mut moves = js_game.moves;
+    moves.push(coord.to_string());
+    Ok(nested_game_to_js(&game, moves))
+}